@@ -0,0 +1,180 @@
+use crate::io;
+use anyhow::{anyhow, bail, Result};
+use ciborium::value::{Integer, Value};
+use quickjs_wasm_rs::{JSContextRef, JSValueRef};
+
+/// CBOR tag of a date as seconds since the unix epoch
+static EPOCH_DATE_TAG: u64 = 1;
+/// CBOR tag of a date as an RFC 3339 string
+static DATE_TAG: u64 = 0;
+/// the largest integer a number represents exactly, `Number.MAX_SAFE_INTEGER`
+static MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+/// Decodes a CBOR payload into a [`JSValueRef`].
+///
+/// Byte strings become a `Uint8Array` and dates (tags 0 and 1) a `Date`. Other tags are
+/// ignored and integers outside the safe integer range of a number become a `BigInt`.
+pub fn decode<'a>(context: &'a JSContextRef, bytes: &[u8]) -> Result<JSValueRef<'a>> {
+    let value: Value = ciborium::from_reader(bytes)?;
+    let helpers = context.global_object()?.get_property("__quickjs")?;
+    to_js(context, &helpers, &value)
+}
+
+/// Encodes a [`JSValueRef`] as CBOR, the reverse of [`decode`].
+///
+/// A `Date` is encoded with tag 1, an `ArrayBuffer` or `Uint8Array` as a byte string and a
+/// `BigInt` or a whole number within the safe integer range as an integer. `undefined` is
+/// encoded as null.
+pub fn encode(context: &JSContextRef, value: JSValueRef) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    ciborium::into_writer(&from_js(context, &value)?, &mut output)?;
+    Ok(output)
+}
+
+fn to_js<'a>(
+    context: &'a JSContextRef,
+    helpers: &JSValueRef<'a>,
+    value: &Value,
+) -> Result<JSValueRef<'a>> {
+    match value {
+        Value::Null => context.null_value(),
+        Value::Bool(value) => context.value_from_bool(*value),
+        // a number when it is exact, a BigInt otherwise
+        Value::Integer(value) => match i64::try_from(i128::from(*value)) {
+            Ok(value) if (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&value) => {
+                context.value_from_i64(value)
+            }
+            _ => {
+                let digits = context.value_from_str(&i128::from(*value).to_string())?;
+                helpers.get_property("bigint")?.call(helpers, &[digits])
+            }
+        },
+        Value::Float(value) => context.value_from_f64(*value),
+        Value::Text(value) => context.value_from_str(value),
+        Value::Bytes(bytes) => {
+            let buffer = context.array_buffer_value(bytes)?;
+            helpers.get_property("bytes")?.call(helpers, &[buffer])
+        }
+        Value::Tag(tag, value) if *tag == EPOCH_DATE_TAG || *tag == DATE_TAG => {
+            let time = match value.as_ref() {
+                Value::Integer(seconds) => {
+                    context.value_from_f64(i128::from(*seconds) as f64 * 1000.0)?
+                }
+                Value::Float(seconds) => context.value_from_f64(seconds * 1000.0)?,
+                Value::Text(date) => context.value_from_str(date)?,
+                value => bail!("invalid CBOR date {value:?}"),
+            };
+            helpers.get_property("date")?.call(helpers, &[time])
+        }
+        Value::Tag(_, value) => to_js(context, helpers, value),
+        Value::Array(values) => {
+            let array = context.array_value()?;
+            for value in values {
+                array.append_property(to_js(context, helpers, value)?)?;
+            }
+            Ok(array)
+        }
+        Value::Map(entries) => {
+            let object = context.object_value()?;
+            for (key, value) in entries {
+                let key = match key {
+                    Value::Text(key) => key.clone(),
+                    Value::Integer(key) => i128::from(*key).to_string(),
+                    key => bail!("CBOR map key {key:?} cannot be an object key"),
+                };
+                object.set_property(key, to_js(context, helpers, value)?)?;
+            }
+            Ok(object)
+        }
+        value => bail!("unsupported CBOR value {value:?}"),
+    }
+}
+
+fn from_js(context: &JSContextRef, value: &JSValueRef) -> Result<Value> {
+    if value.is_null_or_undefined() {
+        return Ok(Value::Null);
+    }
+    if value.is_bool() {
+        return Ok(Value::Bool(value.as_bool()?));
+    }
+    if value.is_repr_as_i32() {
+        return Ok(Value::Integer(Integer::from(value.as_i32_unchecked())));
+    }
+    if value.is_repr_as_f64() {
+        // whole numbers outside the i32 range (i.e. `2 ** 40`) are still integers
+        let number = value.as_f64_unchecked();
+        if number.fract() == 0.0 && number.abs() <= MAX_SAFE_INTEGER as f64 {
+            return Ok(Value::Integer(Integer::from(number as i64)));
+        }
+        return Ok(Value::Float(number));
+    }
+    if value.is_big_int() {
+        let digits = value.get_property("toString")?.call(value, &[])?;
+        let integer = digits.as_str()?.parse::<i128>()?;
+        return Integer::try_from(integer)
+            .map(Value::Integer)
+            .map_err(|_| anyhow!("BigInt {integer} cannot be encoded as a CBOR integer"));
+    }
+    if value.is_str() {
+        return Ok(Value::Text(value.as_str()?.to_string()));
+    }
+    if value.is_array_buffer() {
+        return Ok(Value::Bytes(value.as_bytes()?.to_vec()));
+    }
+    if value.is_array() {
+        let length = value.get_property("length")?.as_i32_unchecked() as u32;
+        return (0..length)
+            .map(|index| from_js(context, &value.get_indexed_property(index)?))
+            .collect::<Result<_>>()
+            .map(Value::Array);
+    }
+    if value.is_object() {
+        if let Some(value) = from_js_builtin(context, value)? {
+            return Ok(value);
+        }
+
+        let mut entries = Vec::new();
+        let mut properties = value.properties()?;
+        while let Some(key) = properties.next_key()? {
+            let property = properties.next_value()?;
+            // skipped like JSON.stringify does
+            if property.is_undefined() || property.is_function() {
+                continue;
+            }
+            entries.push((
+                Value::Text(key.as_str()?.to_string()),
+                from_js(context, &property)?,
+            ));
+        }
+        return Ok(Value::Map(entries));
+    }
+    Err(anyhow!("{value} cannot be encoded as CBOR"))
+}
+
+/// a `Date` or `Uint8Array`, recognized by their constructor
+fn from_js_builtin(context: &JSContextRef, value: &JSValueRef) -> Result<Option<Value>> {
+    let constructor = value.get_property("constructor")?;
+    if !constructor.is_function() {
+        return Ok(None);
+    }
+
+    match constructor.get_property("name")?.as_str()? {
+        "Date" => {
+            let time = value.get_property("getTime")?.call(value, &[])?;
+            let time = if time.is_repr_as_i32() {
+                f64::from(time.as_i32_unchecked())
+            } else {
+                time.as_f64_unchecked()
+            };
+            let seconds = if time % 1000.0 == 0.0 {
+                Value::Integer(Integer::from((time / 1000.0) as i64))
+            } else {
+                Value::Float(time / 1000.0)
+            };
+            Ok(Some(Value::Tag(EPOCH_DATE_TAG, Box::new(seconds))))
+        }
+        // an object only named like one is encoded as a map
+        "Uint8Array" => Ok(io::view_bytes(context, *value)?.map(Value::Bytes)),
+        _ => Ok(None),
+    }
+}
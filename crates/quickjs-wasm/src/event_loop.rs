@@ -0,0 +1,41 @@
+use anyhow::{bail, Result};
+use quickjs_wasm_rs::{from_qjs_value, JSContextRef, JSValue};
+
+#[link(wasm_import_module = "host")]
+extern "C" {
+    fn clock_now() -> f64;
+    fn clock_wait_until(due: f64);
+}
+
+/// connects the timers of the prelude to the host clock of this execution
+pub fn install(context: &JSContextRef) -> Result<()> {
+    let clock = context.wrap_callback(|_, _, _| Ok(JSValue::Float(unsafe { clock_now() })))?;
+
+    let helpers = context.global_object()?.get_property("__quickjs")?;
+    helpers.get_property("setClock")?.call(&helpers, &[clock])?;
+    Ok(())
+}
+
+/// runs pending jobs and timers until neither remain.
+///
+/// the host waits for each timer to be due, or fails once it would fire after the time limit.
+pub fn run(context: &JSContextRef) -> Result<()> {
+    let helpers = context.global_object()?.get_property("__quickjs")?;
+
+    loop {
+        while context.is_pending() {
+            context.execute_pending()?;
+        }
+
+        // timers are due at integer or fractional milliseconds
+        let due = match from_qjs_value(helpers.get_property("nextTimer")?.call(&helpers, &[])?)? {
+            JSValue::Undefined => return Ok(()),
+            JSValue::Int(due) => f64::from(due),
+            JSValue::Float(due) => due,
+            due => bail!("invalid timer due time {due:?}"),
+        };
+
+        unsafe { clock_wait_until(due) };
+        helpers.get_property("runTimer")?.call(&helpers, &[])?;
+    }
+}
@@ -0,0 +1,63 @@
+use crate::io::{self, WireFormat};
+use anyhow::{bail, Result};
+use quickjs_wasm_rs::{from_qjs_value, JSContextRef, JSValue, JSValueRef};
+
+#[link(wasm_import_module = "host")]
+extern "C" {
+    fn get_host_functions(ptr: i32);
+    fn get_host_functions_size() -> i32;
+    fn call_host_function(name_ptr: i32, name_size: i32, args_ptr: i32, args_size: i32) -> i32;
+    fn get_host_result(ptr: i32);
+    fn get_host_result_size() -> i32;
+}
+
+/// binds the functions registered on the host to the global `host` namespace
+pub fn set_host_functions(context: &JSContextRef) -> Result<()> {
+    let names = match io::read_from_host(unsafe { get_host_functions_size() }, get_host_functions) {
+        Some(names) => serde_json::from_slice::<Vec<String>>(&names)?,
+        None => return Ok(()),
+    };
+
+    let host = context.object_value()?;
+    for name in names {
+        let callback = context.wrap_callback(host_function(name.clone()))?;
+        host.set_property(name.as_str(), callback)?;
+    }
+    context.global_object()?.set_property("host", host)?;
+
+    Ok(())
+}
+
+/// host_function marshals the javascript arguments as a JSON array to the host function `name`
+/// and transcodes its result. an error returned by the host is thrown as an exception.
+fn host_function(
+    name: String,
+) -> impl FnMut(&JSContextRef, JSValueRef, &[JSValueRef]) -> Result<JSValue> {
+    move |context: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
+        let mut payload = vec![b'['];
+        for (i, arg) in args.iter().enumerate() {
+            if i != 0 {
+                payload.push(b',');
+            }
+            payload.extend(io::transcode_output(context, *arg, WireFormat::Json)?);
+        }
+        payload.push(b']');
+
+        let status = unsafe {
+            call_host_function(
+                name.as_ptr() as i32,
+                name.len() as i32,
+                payload.as_ptr() as i32,
+                payload.len() as i32,
+            )
+        };
+        let result = io::read_from_host(unsafe { get_host_result_size() }, get_host_result)
+            .unwrap_or_default();
+
+        if status != 0 {
+            bail!("{}", String::from_utf8_lossy(&result));
+        }
+
+        from_qjs_value(io::transcode_input(context, &result, WireFormat::Json)?)
+    }
+}
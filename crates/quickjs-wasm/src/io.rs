@@ -1,98 +1,418 @@
-use anyhow::Result;
-use quickjs_wasm_rs::{Deserializer, JSContextRef, JSValueRef, Serializer};
+use crate::{cbor, modules};
+use anyhow::{anyhow, Result};
+use quickjs_wasm_rs::{
+    quickjs_wasm_sys::{JSRuntime, JS_NewArrayBuffer},
+    Deserializer, Exception, JSContextRef, JSValueRef, Serializer,
+};
+use serde::Deserialize;
+use std::{ffi::c_void, fmt, ptr};
 
 #[link(wasm_import_module = "host")]
 extern "C" {
     fn get_script(ptr: i32);
     fn get_script_size() -> i32;
+    fn get_script_kind() -> i32;
+    fn get_script_name(ptr: i32);
+    fn get_script_name_size() -> i32;
     fn get_data(ptr: i32);
     fn get_data_size() -> i32;
+    fn get_function(ptr: i32);
+    fn get_function_size() -> i32;
+    fn get_modules(ptr: i32);
+    fn get_modules_size() -> i32;
+    fn get_wire_format() -> i32;
+    fn get_inputs(ptr: i32);
+    fn get_inputs_size() -> i32;
+    fn has_lazy_data() -> i32;
     fn set_output(ptr: i32, size: i32, error: i32);
 }
 
-/// Transcodes a byte slice containing a JSON encoded payload into a [`JSValueRef`].
+/// The kind of payload passed to the host with `set_output`.
+#[derive(Clone, Copy)]
+#[repr(i32)]
+enum OutputKind {
+    Value = 0,
+    Exception = 1,
+    FunctionNotFound = 2,
+    NotCallable = 3,
+}
+
+/// How the host wants the script to be treated.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ScriptKind {
+    /// evaluate the source
+    Source,
+    /// evaluate bytecode produced by [`ScriptKind::Compile`]
+    Bytecode,
+    /// compile the source and output its bytecode without evaluating it
+    Compile,
+}
+
+/// How the host encodes the input data and wants the output value encoded.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum WireFormat {
+    Json,
+    MessagePack,
+    Cbor,
+    /// raw bytes passed as a `Uint8Array` and returned from an `ArrayBuffer` or typed array
+    Binary,
+}
+
+/// Failure to look up the function requested by the host.
+#[derive(Debug)]
+pub enum CallError {
+    NotFound(String),
+    NotCallable(String),
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(name) => write!(f, "{name} is not defined"),
+            Self::NotCallable(name) => write!(f, "{name} is not a function"),
+        }
+    }
+}
+
+impl std::error::Error for CallError {}
+
+/// The ES module the host wants evaluated and the export it wants as the output.
+#[derive(Deserialize)]
+pub struct EntryModule {
+    pub name: String,
+    pub export: String,
+}
+
+/// A named input the host passes as an element of the data array.
+#[derive(Deserialize)]
+pub struct InputName {
+    pub name: String,
+    pub frozen: bool,
+}
+
+/// Transcodes a byte slice containing a payload encoded in `format` into a [`JSValueRef`].
 ///
 /// Arguments:
 /// * `context` - A reference to the [`JSContextRef`] that will contain the
 ///   returned [`JSValueRef`].
-/// * `bytes` - A byte slice containing a JSON encoded payload.
-pub fn transcode_input<'a>(context: &'a JSContextRef, bytes: &[u8]) -> Result<JSValueRef<'a>> {
-    let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+/// * `bytes` - A byte slice containing an encoded payload.
+/// * `format` - The encoding of `bytes`.
+pub fn transcode_input<'a>(
+    context: &'a JSContextRef,
+    bytes: &[u8],
+    format: WireFormat,
+) -> Result<JSValueRef<'a>> {
     let mut serializer = Serializer::from_context(context)?;
-    serde_transcode::transcode(&mut deserializer, &mut serializer)?;
+    match format {
+        WireFormat::Json => {
+            let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+            serde_transcode::transcode(&mut deserializer, &mut serializer)?;
+        }
+        WireFormat::MessagePack => {
+            let mut deserializer = rmp_serde::Deserializer::from_read_ref(bytes);
+            serde_transcode::transcode(&mut deserializer, &mut serializer)?;
+        }
+        // tagged values are not representable by serde
+        WireFormat::Cbor => return cbor::decode(context, bytes),
+        WireFormat::Binary => return uint8_array(context, context.array_buffer_value(bytes)?),
+    }
     Ok(serializer.value)
 }
 
-/// Transcodes a [`JSValueRef`] into a JSON encoded byte vector.
-pub fn transcode_output(val: JSValueRef) -> Result<Vec<u8>> {
+/// Transcodes a [`JSValueRef`] into a byte vector encoded in `format`.
+///
+/// A value that cannot be encoded as binary fails with a `TypeError` exception.
+pub fn transcode_output(
+    context: &JSContextRef,
+    val: JSValueRef,
+    format: WireFormat,
+) -> Result<Vec<u8>> {
+    match format {
+        WireFormat::Cbor => return cbor::encode(context, val),
+        WireFormat::Binary => return binary_output(context, val),
+        _ => {}
+    }
+
     let mut output = Vec::new();
     let mut deserializer = Deserializer::from(val);
-    let mut serializer = serde_json::Serializer::new(&mut output);
-    serde_transcode::transcode(&mut deserializer, &mut serializer)?;
+    match format {
+        WireFormat::Json => {
+            let mut serializer = serde_json::Serializer::new(&mut output);
+            serde_transcode::transcode(&mut deserializer, &mut serializer)?;
+        }
+        WireFormat::MessagePack => {
+            let mut serializer = rmp_serde::Serializer::new(&mut output);
+            serde_transcode::transcode(&mut deserializer, &mut serializer)?;
+        }
+        WireFormat::Cbor | WireFormat::Binary => unreachable!(),
+    }
     Ok(output)
 }
 
-/// gets the script from the host as a string
-pub fn get_input_script() -> Result<Option<String>> {
-    let input_size = unsafe { get_script_size() } as usize;
+/// Copies the bytes of an `ArrayBuffer` or a view of one, i.e. a `Uint8Array` or `DataView`.
+/// `undefined` and `null` have no bytes.
+fn binary_output(context: &JSContextRef, val: JSValueRef) -> Result<Vec<u8>> {
+    if val.is_null_or_undefined() {
+        return Ok(Vec::new());
+    }
+    if val.is_array_buffer() {
+        return Ok(val.as_bytes()?.to_vec());
+    }
+    if let Some(bytes) = view_bytes(context, val)? {
+        return Ok(bytes);
+    }
+    Err(type_error(
+        context,
+        "binary output must be an ArrayBuffer or typed array",
+    )?)
+}
+
+/// Copies the bytes viewed by a typed array or `DataView`, none if `val` is neither.
+///
+/// The view is recognized and read from its internal slots by the prelude, so an object
+/// pretending to be one (i.e. with its own `byteOffset`) is not.
+pub fn view_bytes(context: &JSContextRef, val: JSValueRef) -> Result<Option<Vec<u8>>> {
+    let helpers = context.global_object()?.get_property("__quickjs")?;
+    let view = helpers.get_property("view")?.call(&helpers, &[val])?;
+    if view.is_undefined() {
+        return Ok(None);
+    }
+
+    let out_of_bounds = || anyhow!("typed array is out of the bounds of its buffer");
+    let index = |index: u32| -> Result<usize> {
+        let value = view.get_indexed_property(index)?;
+        if !value.is_repr_as_i32() {
+            return Err(out_of_bounds());
+        }
+        usize::try_from(value.as_i32_unchecked()).map_err(|_| out_of_bounds())
+    };
+    let offset = index(1)?;
+    let end = offset.checked_add(index(2)?).ok_or_else(out_of_bounds)?;
+
+    view.get_indexed_property(0)?
+        .as_bytes()?
+        .get(offset..end)
+        .map(|bytes| Some(bytes.to_vec()))
+        .ok_or_else(out_of_bounds)
+}
+
+/// a `Uint8Array` over `buffer`
+fn uint8_array<'a>(context: &'a JSContextRef, buffer: JSValueRef<'a>) -> Result<JSValueRef<'a>> {
+    let helpers = context.global_object()?.get_property("__quickjs")?;
+    helpers.get_property("bytes")?.call(&helpers, &[buffer])
+}
+
+/// a `TypeError` with `message` as an uncaught exception
+fn type_error(context: &JSContextRef, message: &str) -> Result<anyhow::Error> {
+    let helpers = context.global_object()?.get_property("__quickjs")?;
+    let error = helpers
+        .get_property("typeError")?
+        .call(&helpers, &[context.value_from_str(message)?])?;
+    Ok(Exception::from(error)?.into_error())
+}
+
+/// Serializes an uncaught exception into a JSON encoded `{ name, message, stack }` object.
+///
+/// quickjs-wasm-rs formats exceptions as `Uncaught <name>: <message>` followed by the stack on
+/// the following lines.
+pub fn serialize_exception(err: &anyhow::Error) -> Result<Vec<u8>> {
+    let err = err.to_string();
+    let err = err.strip_prefix("Uncaught ").unwrap_or(&err);
+
+    let (head, stack) = match err.split_once('\n') {
+        Some((head, stack)) if !stack.trim().is_empty() => (head, Some(stack)),
+        Some((head, _)) => (head, None),
+        None => (err, None),
+    };
+    let (name, message) = match head.split_once(": ") {
+        Some((name, message)) if !name.is_empty() && !name.contains(char::is_whitespace) => {
+            (name, message)
+        }
+        _ => ("Error", head),
+    };
+
+    Ok(serde_json::to_vec(&serde_json::json!({
+        "name": name,
+        "message": message,
+        "stack": stack,
+    }))?)
+}
+
+/// reads `size` bytes written by the host with `read`
+pub fn read_from_host(size: i32, read: unsafe extern "C" fn(i32)) -> Option<Vec<u8>> {
+    let size = size as usize;
 
-    if input_size == 0 {
-        Ok(None)
+    if size == 0 {
+        None
     } else {
-        let mut buf: Vec<u8> = Vec::with_capacity(input_size);
-        let ptr = buf.as_mut_ptr();
-        unsafe { get_script(ptr as i32) };
+        let mut buf: Vec<u8> = Vec::with_capacity(size);
+        unsafe {
+            read(buf.as_mut_ptr() as i32);
+            buf.set_len(size);
+        }
+        Some(buf)
+    }
+}
+
+/// gets the script from the host, either source or bytecode depending on [`get_script_kind`]
+pub fn get_input_script() -> Option<Vec<u8>> {
+    read_from_host(unsafe { get_script_size() }, get_script)
+}
+
+/// gets how the host wants the script to be treated
+pub fn get_script_kind() -> ScriptKind {
+    match unsafe { get_script_kind() } {
+        1 => ScriptKind::Bytecode,
+        2 => ScriptKind::Compile,
+        _ => ScriptKind::Source,
+    }
+}
 
-        let input_buf = unsafe { Vec::from_raw_parts(ptr, input_size, input_size) };
+/// gets the name to compile the script with, if any
+pub fn get_input_script_name() -> Result<Option<String>> {
+    read_from_host(unsafe { get_script_name_size() }, get_script_name)
+        .map(String::from_utf8)
+        .transpose()
+        .map_err(Into::into)
+}
 
-        Ok(Some(String::from_utf8(input_buf.to_vec())?))
+/// gets how the host encodes the data and wants the output value encoded
+pub fn get_wire_format() -> WireFormat {
+    match unsafe { get_wire_format() } {
+        1 => WireFormat::MessagePack,
+        2 => WireFormat::Cbor,
+        3 => WireFormat::Binary,
+        _ => WireFormat::Json,
     }
 }
 
 /// gets the data from the host as a JSValueRef
+///
+/// binary data is always bound, empty data as an empty `Uint8Array`.
 pub fn get_input_data(context: &JSContextRef) -> Result<Option<JSValueRef>> {
-    let input_size = unsafe { get_data_size() } as usize;
+    let format = get_wire_format();
+    if format == WireFormat::Binary {
+        return get_binary_data(context).map(Some);
+    }
+    read_from_host(unsafe { get_data_size() }, get_data)
+        .map(|input| transcode_input(context, &input, format))
+        .transpose()
+}
 
-    if input_size == 0 {
-        Ok(None)
-    } else {
-        let mut buf: Vec<u8> = Vec::with_capacity(input_size);
-        let ptr = buf.as_mut_ptr();
-        unsafe { get_data(ptr as i32) };
+/// gets the binary data from the host as a `Uint8Array` over the buffer the host writes it to,
+/// so the bytes are only copied once
+fn get_binary_data(context: &JSContextRef) -> Result<JSValueRef> {
+    let size = unsafe { get_data_size() } as usize;
+    if size == 0 {
+        return uint8_array(context, context.array_buffer_value(&[])?);
+    }
 
-        let input_buf = unsafe { Vec::from_raw_parts(ptr, input_size, input_size) };
+    let mut bytes = Vec::with_capacity(size);
+    let bytes = unsafe {
+        get_data(bytes.as_mut_ptr() as i32);
+        bytes.set_len(size);
+        Box::into_raw(bytes.into_boxed_slice()) as *mut u8
+    };
 
-        Ok(Some(transcode_input(context, &input_buf)?))
+    // the ArrayBuffer owns the bytes and frees them with `free_binary_data`, the size is passed
+    // as its opaque pointer
+    let buffer = modules::checked(context, unsafe {
+        JS_NewArrayBuffer(
+            context.as_raw(),
+            bytes,
+            size as _,
+            Some(free_binary_data),
+            size as *mut c_void,
+            0,
+        )
+    });
+    match buffer {
+        Ok(buffer) => uint8_array(context, buffer),
+        // quickjs only takes ownership of the bytes once the buffer is created
+        Err(err) => {
+            unsafe { free_binary_data(ptr::null_mut(), size as *mut c_void, bytes as *mut c_void) };
+            Err(err)
+        }
     }
 }
 
-/// sets the output value on the host
-pub fn set_output_value(output: Result<Option<JSValueRef>>) -> Result<()> {
+/// frees the bytes of an `ArrayBuffer` created by [`get_binary_data`]
+unsafe extern "C" fn free_binary_data(
+    _runtime: *mut JSRuntime,
+    size: *mut c_void,
+    bytes: *mut c_void,
+) {
+    drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+        bytes as *mut u8,
+        size as usize,
+    )));
+}
+
+/// gets the name of the function the host wants to call, if any
+pub fn get_input_function() -> Result<Option<String>> {
+    read_from_host(unsafe { get_function_size() }, get_function)
+        .map(String::from_utf8)
+        .transpose()
+        .map_err(Into::into)
+}
+
+/// gets the ES module to evaluate instead of a script, if any
+pub fn get_input_module() -> Result<Option<EntryModule>> {
+    read_from_host(unsafe { get_modules_size() }, get_modules)
+        .map(|modules| serde_json::from_slice(&modules))
+        .transpose()
+        .map_err(Into::into)
+}
+
+/// whether the host resolves `data` lazily, see [`crate::lazy`]
+pub fn has_lazy_data() -> bool {
+    unsafe { has_lazy_data() != 0 }
+}
+
+/// gets the names the elements of the data array are bound to instead of `data`, if any
+pub fn get_input_names() -> Result<Option<Vec<InputName>>> {
+    read_from_host(unsafe { get_inputs_size() }, get_inputs)
+        .map(|names| serde_json::from_slice(&names))
+        .transpose()
+        .map_err(Into::into)
+}
+
+/// sets the output value encoded with [`transcode_output`] on the host
+pub fn set_output_value(output: Result<Option<Vec<u8>>>) -> Result<()> {
     match output {
-        Ok(None) => unsafe {
-            set_output(0, 0, 0);
-        },
-        Ok(Some(output)) => {
-            let output = transcode_output(output)?;
-
-            let size = output.len() as i32;
-            let ptr = output.as_ptr();
-
-            unsafe {
-                set_output(ptr as i32, size, 0);
-            }
-        }
-        Err(err) => {
-            let err = err.to_string();
+        Ok(output) => write_output(OutputKind::Value, &output.unwrap_or_default()),
+        Err(err) => write_error(err)?,
+    }
+    Ok(())
+}
 
-            let output = err.as_bytes();
-            let size = output.len() as i32;
-            let ptr = output.as_ptr();
+/// sets the compiled bytecode as the output on the host
+pub fn set_output_bytecode(output: Result<Vec<u8>>) -> Result<()> {
+    match output {
+        Ok(bytecode) => write_output(OutputKind::Value, &bytecode),
+        Err(err) => write_error(err)?,
+    }
+    Ok(())
+}
 
-            unsafe {
-                set_output(ptr as i32, size, 1);
-            };
+fn write_error(err: anyhow::Error) -> Result<()> {
+    match err.downcast_ref::<CallError>() {
+        Some(CallError::NotFound(name)) => {
+            write_output(OutputKind::FunctionNotFound, name.as_bytes())
         }
+        Some(CallError::NotCallable(name)) => {
+            write_output(OutputKind::NotCallable, name.as_bytes())
+        }
+        None => write_output(OutputKind::Exception, &serialize_exception(&err)?),
     }
     Ok(())
 }
+
+fn write_output(kind: OutputKind, output: &[u8]) {
+    let size = output.len() as i32;
+    let ptr = output.as_ptr();
+
+    unsafe {
+        set_output(ptr as i32, size, kind as i32);
+    }
+}
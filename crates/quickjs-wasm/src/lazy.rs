@@ -0,0 +1,37 @@
+use crate::io::{self, WireFormat};
+use anyhow::{bail, Result};
+use quickjs_wasm_rs::{from_qjs_value, JSContextRef, JSValueRef};
+
+#[link(wasm_import_module = "host")]
+extern "C" {
+    fn get_lazy_data(path_ptr: i32, path_size: i32) -> i32;
+    fn get_host_result(ptr: i32);
+    fn get_host_result_size() -> i32;
+}
+
+/// binds the global `data` to a read-only proxy resolving each value read by the script on the
+/// host, so only the values actually touched are copied into the guest
+pub fn bind(context: &JSContextRef) -> Result<()> {
+    let get = context.wrap_callback(
+        |context: &JSContextRef, _this: JSValueRef, args: &[JSValueRef]| {
+            let path = match args.first() {
+                Some(path) => io::transcode_output(context, *path, WireFormat::Json)?,
+                None => bail!("missing path"),
+            };
+
+            let status = unsafe { get_lazy_data(path.as_ptr() as i32, path.len() as i32) };
+            let result = io::read_from_host(unsafe { get_host_result_size() }, get_host_result)
+                .unwrap_or_default();
+            if status != 0 {
+                bail!("{}", String::from_utf8_lossy(&result));
+            }
+
+            from_qjs_value(io::transcode_input(context, &result, WireFormat::Json)?)
+        },
+    )?;
+
+    let helpers = context.global_object()?.get_property("__quickjs")?;
+    let data = helpers.get_property("lazy")?.call(&helpers, &[get])?;
+    context.global_object()?.set_property("data", data)?;
+    Ok(())
+}
@@ -1,14 +1,25 @@
+mod cbor;
 #[cfg(feature = "console")]
 mod context;
+mod event_loop;
+mod host;
 mod io;
+mod lazy;
+mod modules;
+mod promise;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use io::{CallError, ScriptKind};
 use once_cell::sync::OnceCell;
-use quickjs_wasm_rs::JSContextRef;
+use quickjs_wasm_rs::{JSContextRef, JSValueRef};
+use std::{fs, path::Path};
 
 static mut JS_CONTEXT: OnceCell<JSContextRef> = OnceCell::new();
 static SCRIPT_NAME: &str = "script.js";
 static DEPENDENCIES: &str = include_str!("../dependencies/index.js");
+pub static PRELUDE_NAME: &str = "prelude.js";
+static PRELUDE: &str = include_str!("prelude.js");
+static SPECIALIZE_DIR: &str = "/specialize";
 
 /// init() is executed by wizer to create a snapshot after the quickjs context has been initialized.
 ///
@@ -19,7 +30,8 @@ pub extern "C" fn init() {
     unsafe {
         let context = JSContextRef::default();
 
-        // add any init code
+        // add the internal helpers and any init code
+        context.eval_global(PRELUDE_NAME, PRELUDE).unwrap();
         context.eval_global(SCRIPT_NAME, DEPENDENCIES).unwrap();
 
         // add globals to the quickjs instance if enabled
@@ -30,17 +42,193 @@ pub extern "C" fn init() {
     }
 }
 
-fn main() -> Result<()> {
-    match io::get_input_script()? {
-        Some(input) => {
+/// specialize() is executed by wizer at runtime to snapshot the context after evaluating a
+/// plugin script, so its top-level state is already built in every execution of the snapshot.
+///
+/// the script is read from `/specialize/script.js`. an exception is written to
+/// `/specialize/error` before aborting the initialization.
+#[export_name = "quickjs.specialize"]
+pub extern "C" fn specialize() {
+    let dir = Path::new(SPECIALIZE_DIR);
+
+    let result = fs::read_to_string(dir.join(SCRIPT_NAME))
+        .map_err(anyhow::Error::from)
+        .and_then(|script| {
             let context = unsafe { JS_CONTEXT.get_or_init(JSContextRef::default) };
+            context.eval_global(SCRIPT_NAME, &script)?;
 
-            if let Some(value) = io::get_input_data(context)? {
-                context.global_object()?.set_property("data", value)?;
+            // timers need the host clock which is unavailable while initializing
+            while context.is_pending() {
+                context.execute_pending()?;
             }
+            Ok(())
+        });
 
-            io::set_output_value(context.eval_global(SCRIPT_NAME, &input).map(Some))
+    if let Err(err) = result {
+        if let Ok(exception) = io::serialize_exception(&err) {
+            fs::write(dir.join("error"), exception).ok();
         }
-        None => io::set_output_value(Ok(None)),
+        std::process::abort();
     }
 }
+
+fn main() -> Result<()> {
+    execute()
+}
+
+/// run() executes the invocation of the host on the persistent context of a session, so globals
+/// defined by previous invocations are still available.
+#[export_name = "quickjs.run"]
+pub extern "C" fn run() {
+    if let Err(err) = execute() {
+        io::set_output_value(Err(err)).ok();
+    }
+}
+
+/// evaluates the input of the host and sets its output
+fn execute() -> Result<()> {
+    let module = io::get_input_module()?;
+    let function = io::get_input_function()?;
+    let script = io::get_input_script();
+    // a function can be called without a script i.e. from a specialized snapshot
+    if module.is_none() && function.is_none() && script.is_none() {
+        return io::set_output_value(Ok(None));
+    }
+    let script = script.unwrap_or_default();
+
+    let context = unsafe { JS_CONTEXT.get_or_init(JSContextRef::default) };
+
+    let kind = io::get_script_kind();
+    if kind == ScriptKind::Compile {
+        let name = io::get_input_script_name()?.unwrap_or_else(|| SCRIPT_NAME.to_string());
+        return io::set_output_bytecode(compile(context, &name, &String::from_utf8(script)?));
+    }
+
+    host::set_host_functions(context)?;
+    event_loop::install(context)?;
+    modules::install(context);
+
+    let output = match (module, function) {
+        (Some(module), _) => {
+            bind_data(context)?;
+            modules::evaluate(context, &module)
+        }
+        (None, Some(function)) => call(context, &String::from_utf8(script)?, &function),
+        (None, None) => {
+            bind_data(context)?;
+            match kind {
+                ScriptKind::Bytecode => {
+                    modules::eval_compiled(context, modules::read_bytecode(context, &script)?)
+                }
+                _ => eval(context, &String::from_utf8(script)?),
+            }
+        }
+    };
+    // run queued jobs and timers then unwrap a returned promise
+    let output = output
+        .and_then(|value| promise::settle(context, value))
+        .and_then(|value| io::transcode_output(context, value, io::get_wire_format()))
+        .map(Some);
+
+    io::set_output_value(output)
+}
+
+/// binds the data passed by the host to the global `data` or each named input to its own global
+fn bind_data(context: &JSContextRef) -> Result<()> {
+    if io::has_lazy_data() {
+        return lazy::bind(context);
+    }
+
+    let global = context.global_object()?;
+    match (io::get_input_names()?, io::get_input_data(context)?) {
+        (Some(names), Some(values)) => {
+            // a session may still hold the data of a previous call
+            global.set_property("data", context.undefined_value()?)?;
+            for (index, input) in names.iter().enumerate() {
+                let value = values.get_indexed_property(index as u32)?;
+                if input.frozen {
+                    freeze(context, value)?;
+                }
+                global.set_property(input.name.as_str(), value)?;
+            }
+        }
+        (None, Some(value)) => global.set_property("data", value)?,
+        (_, None) => global.set_property("data", context.undefined_value()?)?,
+    }
+    Ok(())
+}
+
+/// `Object.freeze` a value so the script cannot modify it
+fn freeze<'a>(context: &'a JSContextRef, value: JSValueRef<'a>) -> Result<()> {
+    let helpers = context.global_object()?.get_property("__quickjs")?;
+    helpers.get_property("freeze")?.call(&helpers, &[value])?;
+    Ok(())
+}
+
+/// evaluates `script` in the global scope.
+///
+/// a script using top-level `await` is evaluated as an ES module instead, its result is then a
+/// promise of its default export.
+fn eval<'a>(context: &'a JSContextRef, script: &str) -> Result<JSValueRef<'a>> {
+    modules::eval_compiled(context, modules::compile(context, SCRIPT_NAME, script)?)
+}
+
+/// compiles `script` to bytecode like [`eval`] would evaluate it
+fn compile(context: &JSContextRef, name: &str, script: &str) -> Result<Vec<u8>> {
+    modules::write_bytecode(context, modules::compile(context, name, script)?)
+}
+
+/// evaluates `script` then calls the function `name` with the arguments passed by the host as
+/// data.
+///
+/// the function is looked up as a global (including top-level `let`/`const` bindings) or as a
+/// property of the value the script evaluates to i.e. `({ transform })`. the value of a script
+/// evaluated as a module is its namespace, so its exports are found once its top-level `await`s
+/// settled.
+fn call<'a>(context: &'a JSContextRef, script: &str, name: &str) -> Result<JSValueRef<'a>> {
+    let completion =
+        modules::eval_compiled_exports(context, modules::compile(context, SCRIPT_NAME, script)?)?;
+
+    let mut function = if is_identifier(name) {
+        context.eval_global(
+            SCRIPT_NAME,
+            &format!("typeof {name} === 'undefined' ? undefined : {name}"),
+        )?
+    } else {
+        context.global_object()?.get_property(name)?
+    };
+    if function.is_undefined() && completion.is_object() {
+        function = completion.get_property(name)?;
+    }
+
+    if function.is_undefined() {
+        bail!(CallError::NotFound(name.to_string()));
+    }
+    if !function.is_function() {
+        bail!(CallError::NotCallable(name.to_string()));
+    }
+
+    let args = match io::get_input_data(context)? {
+        Some(args) => args,
+        None => context.array_value()?,
+    };
+    // named inputs are passed as the arguments in order
+    let names = io::get_input_names()?.unwrap_or_default();
+    for (index, input) in names.iter().enumerate() {
+        if input.frozen {
+            freeze(context, args.get_indexed_property(index as u32)?)?;
+        }
+    }
+
+    // spread the arguments array with Function.prototype.apply
+    function
+        .get_property("apply")?
+        .call(&function, &[context.undefined_value()?, args])
+}
+
+/// whether `name` can be referenced directly in javascript source
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
@@ -0,0 +1,307 @@
+use crate::{
+    io::{self, EntryModule},
+    promise, PRELUDE_NAME,
+};
+use anyhow::Result;
+use quickjs_wasm_rs::{
+    quickjs_wasm_sys::{
+        js_malloc, JSContext, JSModuleDef, JSValue, JS_Eval, JS_EvalFunction, JS_GetException,
+        JS_GetRuntime, JS_ReadObject, JS_SetModuleLoaderFunc, JS_Throw, JS_ThrowInternalError,
+        JS_WriteObject, JS_EVAL_FLAG_COMPILE_ONLY, JS_EVAL_TYPE_GLOBAL, JS_EVAL_TYPE_MODULE,
+        JS_READ_OBJ_BYTECODE, JS_TAG_MODULE, JS_WRITE_OBJ_BYTECODE,
+    },
+    Exception, JSContextRef, JSValueRef,
+};
+use std::{
+    cell::{Cell, RefCell},
+    ffi::{c_char, c_void, CStr, CString},
+    ptr,
+};
+
+/// the specifier a script compiled as a module is imported with by [`eval_compiled`]
+static INLINE_NAME: &str = "quickjs:inline";
+
+thread_local! {
+    /// the compiled script the loader returns when [`INLINE_NAME`] is imported
+    static INLINE_MODULE: Cell<*mut JSModuleDef> = const { Cell::new(ptr::null_mut()) };
+    /// the module that imported the last normalized name, quickjs loads a module right after
+    /// normalizing its name
+    static IMPORTER: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+#[link(wasm_import_module = "host")]
+extern "C" {
+    /// returns 1 if the host has no such module
+    fn resolve_module(name_ptr: i32, name_size: i32) -> i32;
+    fn get_host_result(ptr: i32);
+    fn get_host_result_size() -> i32;
+}
+
+/// installs the module loader of the runtime, which loads every imported module from the host
+/// the first time it is imported.
+///
+/// quickjs resolves the imports itself so static, dynamic (including computed specifiers) and
+/// circular imports all work.
+pub fn install(context: &JSContextRef) {
+    unsafe {
+        let runtime = JS_GetRuntime(context.as_raw());
+        JS_SetModuleLoaderFunc(runtime, Some(normalize), Some(load), ptr::null_mut());
+    }
+}
+
+/// imports the entry module and returns a promise of its requested export
+pub fn evaluate<'a>(context: &'a JSContextRef, entry: &EntryModule) -> Result<JSValueRef<'a>> {
+    let helpers = context.global_object()?.get_property("__quickjs")?;
+    helpers.get_property("importExport")?.call(
+        &helpers,
+        &[
+            context.value_from_str(&entry.name)?,
+            context.value_from_str(&entry.export)?,
+        ],
+    )
+}
+
+/// compiles `script` without evaluating it.
+///
+/// a script that does not parse as a global script (i.e. it uses top-level `await`) is compiled
+/// as an ES module instead, the error of the global script is kept if neither parses.
+pub fn compile<'a>(context: &'a JSContextRef, name: &str, script: &str) -> Result<JSValueRef<'a>> {
+    compile_as(context, name, script, JS_EVAL_TYPE_GLOBAL)
+        .or_else(|err| compile_as(context, name, script, JS_EVAL_TYPE_MODULE).map_err(|_| err))
+}
+
+fn compile_as<'a>(
+    context: &'a JSContextRef,
+    name: &str,
+    script: &str,
+    kind: u32,
+) -> Result<JSValueRef<'a>> {
+    let name = CString::new(name)?;
+    let script = CString::new(script)?;
+    let value = unsafe {
+        JS_Eval(
+            context.as_raw(),
+            script.as_ptr(),
+            script.as_bytes().len() as _,
+            name.as_ptr(),
+            (kind | JS_EVAL_FLAG_COMPILE_ONLY) as i32,
+        )
+    };
+    checked(context, value)
+}
+
+/// serializes a script compiled with [`compile`] to bytecode
+pub fn write_bytecode(context: &JSContextRef, compiled: JSValueRef) -> Result<Vec<u8>> {
+    let mut size = 0;
+    unsafe {
+        let buffer = JS_WriteObject(
+            context.as_raw(),
+            &mut size,
+            compiled.as_raw(),
+            JS_WRITE_OBJ_BYTECODE as i32,
+        );
+        Ok(Vec::from_raw_parts(buffer, size as usize, size as usize))
+    }
+}
+
+/// deserializes bytecode written by [`write_bytecode`]
+pub fn read_bytecode<'a>(context: &'a JSContextRef, bytecode: &[u8]) -> Result<JSValueRef<'a>> {
+    let value = unsafe {
+        JS_ReadObject(
+            context.as_raw(),
+            bytecode.as_ptr(),
+            bytecode.len() as _,
+            JS_READ_OBJ_BYTECODE as i32,
+        )
+    };
+    checked(context, value)
+}
+
+/// evaluates a script compiled with [`compile`]. the result of a global script is its
+/// completion value and the result of a module a promise of its default export.
+pub fn eval_compiled<'a>(
+    context: &'a JSContextRef,
+    compiled: JSValueRef<'a>,
+) -> Result<JSValueRef<'a>> {
+    eval_compiled_with(context, compiled, "importDefault")
+}
+
+/// evaluates a script compiled with [`compile`] to look up its functions. the result of a
+/// global script is its completion value and the result of a module its namespace once its
+/// top-level `await`s settled, so its exports can be called.
+pub fn eval_compiled_exports<'a>(
+    context: &'a JSContextRef,
+    compiled: JSValueRef<'a>,
+) -> Result<JSValueRef<'a>> {
+    let is_module = !module_def(unsafe { compiled.as_raw() }).is_null();
+    let value = eval_compiled_with(context, compiled, "importNamespace")?;
+    if is_module {
+        return promise::settle(context, value);
+    }
+    Ok(value)
+}
+
+/// evaluates a compiled global script or imports a compiled module with the prelude helper
+/// `import`
+fn eval_compiled_with<'a>(
+    context: &'a JSContextRef,
+    compiled: JSValueRef<'a>,
+    import: &str,
+) -> Result<JSValueRef<'a>> {
+    let module = module_def(unsafe { compiled.as_raw() });
+    if module.is_null() {
+        return checked(context, unsafe {
+            JS_EvalFunction(context.as_raw(), compiled.as_raw())
+        });
+    }
+
+    // imported under its own specifier since the name it was compiled with (i.e. `script.js`) may
+    // already be loaded by a previous execution of the session
+    INLINE_MODULE.with(|inline| inline.set(module));
+    let helpers = context.global_object()?.get_property("__quickjs")?;
+    helpers
+        .get_property(import)?
+        .call(&helpers, &[context.value_from_str(INLINE_NAME)?])
+}
+
+/// the value or the pending exception if `value` is an exception
+pub fn checked(context: &JSContextRef, value: JSValue) -> Result<JSValueRef> {
+    let value = unsafe { JSValueRef::from_raw(context, value) };
+    if value.is_exception() {
+        let exception = unsafe { JSValueRef::from_raw(context, JS_GetException(context.as_raw())) };
+        return Err(Exception::from(exception)?.into_error());
+    }
+    Ok(value)
+}
+
+/// the module name normalizer called by quickjs with the name of the importing module. records
+/// the importer for [`load`] and resolves the name like the default normalization does.
+unsafe extern "C" fn normalize(
+    context: *mut JSContext,
+    base: *const c_char,
+    name: *const c_char,
+    _opaque: *mut c_void,
+) -> *mut c_char {
+    let base = CStr::from_ptr(base).to_string_lossy();
+    let name = normalize_name(&base, &CStr::from_ptr(name).to_string_lossy());
+    IMPORTER.with(|importer| importer.replace(base.into_owned()));
+
+    // quickjs frees the name with `js_free`, `js_malloc` raises an exception if it fails
+    let normalized = js_malloc(context, (name.len() + 1) as _) as *mut u8;
+    if !normalized.is_null() {
+        ptr::copy_nonoverlapping(name.as_ptr(), normalized, name.len());
+        *normalized.add(name.len()) = 0;
+    }
+    normalized as *mut c_char
+}
+
+/// resolves a `name` starting with `./` or `../` against the module `base`, other names are
+/// kept as they are
+fn normalize_name(base: &str, name: &str) -> String {
+    if !name.starts_with('.') {
+        return name.to_string();
+    }
+
+    let mut path = base[..base.rfind('/').unwrap_or(0)].to_string();
+    let mut rest = name;
+    loop {
+        if let Some(next) = rest.strip_prefix("./") {
+            rest = next;
+        } else if let Some(next) = rest.strip_prefix("../") {
+            // `..` above the root is kept, like quickjs does
+            let last = path.rfind('/').map_or(0, |index| index + 1);
+            if path.is_empty() || matches!(&path[last..], "." | "..") {
+                break;
+            }
+            path.truncate(last.saturating_sub(1));
+            rest = next;
+        } else {
+            break;
+        }
+    }
+
+    if path.is_empty() {
+        rest.to_string()
+    } else {
+        format!("{path}/{rest}")
+    }
+}
+
+/// the module loader called by quickjs with the normalized name of a module that is not loaded
+/// yet. returns null with a pending exception if the module does not exist or does not compile.
+unsafe extern "C" fn load(
+    context: *mut JSContext,
+    name: *const c_char,
+    _opaque: *mut c_void,
+) -> *mut JSModuleDef {
+    let name = CStr::from_ptr(name);
+    if name.to_bytes() == INLINE_NAME.as_bytes() {
+        let module = INLINE_MODULE.with(|inline| inline.replace(ptr::null_mut()));
+        if !module.is_null() {
+            return module;
+        }
+    }
+
+    let importer = IMPORTER.with(|importer| importer.take());
+    if resolve_module(name.as_ptr() as i32, name.to_bytes().len() as i32) != 0 {
+        // thrown at the import so the importing module can catch it
+        throw_module_not_found(context, &name.to_string_lossy(), &importer);
+        return ptr::null_mut();
+    }
+
+    let mut source =
+        io::read_from_host(get_host_result_size(), get_host_result).unwrap_or_default();
+    let len = source.len();
+    // quickjs requires the source to be null terminated
+    source.push(0);
+
+    let module = JS_Eval(
+        context,
+        source.as_ptr() as *const c_char,
+        len as _,
+        name.as_ptr(),
+        (JS_EVAL_TYPE_MODULE | JS_EVAL_FLAG_COMPILE_ONLY) as i32,
+    );
+    module_def(module)
+}
+
+/// throw the `ModuleNotFoundError` of the prelude for `name` imported by `importer`, the host
+/// reports it as `ExecutionError::ModuleNotFound` if it is not caught
+unsafe fn throw_module_not_found(context: *mut JSContext, name: &str, importer: &str) {
+    let context = JSContextRef::from_raw(context);
+    let error = (|| -> Result<JSValueRef> {
+        let helpers = context.global_object()?.get_property("__quickjs")?;
+        // the entry module is imported by the prelude
+        let importer = if importer == PRELUDE_NAME {
+            context.undefined_value()?
+        } else {
+            context.value_from_str(importer)?
+        };
+        helpers
+            .get_property("moduleNotFound")?
+            .call(&helpers, &[context.value_from_str(name)?, importer])
+    })();
+
+    match error {
+        Ok(error) => {
+            JS_Throw(context.as_raw(), error.as_raw());
+        }
+        // the helper failed (i.e. out of memory) and its exception was taken by the error
+        Err(_) => {
+            let format = CString::new("%s").unwrap();
+            let message = CString::new(format!("cannot find module {name}")).unwrap_or_default();
+            JS_ThrowInternalError(context.as_raw(), format.as_ptr(), message.as_ptr());
+        }
+    }
+}
+
+/// the definition of a compiled module or null if `value` is an exception. quickjs keeps loaded
+/// modules for the lifetime of the context so the reference held by `value` is never released.
+fn module_def(value: JSValue) -> *mut JSModuleDef {
+    // values are NaN-boxed with the tag in the upper 32 bits
+    if (value >> 32) as i32 == JS_TAG_MODULE {
+        value as usize as *mut JSModuleDef
+    } else {
+        ptr::null_mut()
+    }
+}
@@ -0,0 +1,231 @@
+// internal helpers used by quickjs-wasm, hidden from enumeration of the global object
+(() => {
+  // the builtins used by the helpers are captured so inputs or scripts replacing the globals
+  // (i.e. an input named `Object`) cannot break them
+  const {
+    Array,
+    ArrayBuffer,
+    BigInt,
+    DataView,
+    Date,
+    Error,
+    JSON,
+    Map,
+    Math,
+    Number,
+    Object,
+    Promise,
+    Proxy,
+    Reflect,
+    Set,
+    String,
+    SyntaxError,
+    TypeError,
+    Uint8Array,
+  } = globalThis;
+
+  // the getters of the buffer, byteOffset and byteLength slots of typed arrays and data views,
+  // which throw for anything else
+  const viewGetters = (prototype) =>
+    ["buffer", "byteOffset", "byteLength"].map(
+      (name) => Reflect.getOwnPropertyDescriptor(prototype, name).get,
+    );
+  const typedArrayGetters = viewGetters(Object.getPrototypeOf(Uint8Array.prototype));
+  const dataViewGetters = viewGetters(DataView.prototype);
+
+  // pending timers by id, in the order they were scheduled
+  const timers = new Map();
+  let nextId = 1;
+  // milliseconds since the execution started, replaced by the host clock on every execution
+  let now = () => 0;
+
+  const schedule = (callback, delay, args, repeat) => {
+    if (typeof callback !== "function") {
+      throw new TypeError("callback is not a function");
+    }
+    const id = nextId++;
+    delay = Math.max(1, Number(delay) || 0);
+    timers.set(id, { id, callback, args, delay, repeat, due: now() + delay });
+    return id;
+  };
+  const clear = (id) => {
+    timers.delete(id);
+  };
+
+  globalThis.setTimeout = (callback, delay, ...args) => schedule(callback, delay, args, false);
+  globalThis.setInterval = (callback, delay, ...args) => schedule(callback, delay, args, true);
+  globalThis.clearTimeout = clear;
+  globalThis.clearInterval = clear;
+
+  const earliest = () => {
+    let next;
+    for (const timer of timers.values()) {
+      if (next === undefined || timer.due < next.due) {
+        next = timer;
+      }
+    }
+    return next;
+  };
+
+  Object.defineProperty(globalThis, "__quickjs", {
+    value: Object.freeze({
+      setClock(clock) {
+        now = clock;
+      },
+
+      // when the next timer is due or undefined if none remain
+      nextTimer() {
+        return earliest()?.due;
+      },
+
+      // run the earliest timer, the host has waited until it is due
+      runTimer() {
+        const timer = earliest();
+        if (timer === undefined) {
+          return;
+        }
+        if (timer.repeat) {
+          timer.due = now() + timer.delay;
+        } else {
+          timers.delete(timer.id);
+        }
+        timer.callback(...timer.args);
+      },
+
+      // the export `name` of the module `specifier`, loaded on its first import
+      importExport(specifier, name) {
+        return import(specifier).then((module) => {
+          if (!(name in module)) {
+            throw new SyntaxError(`module ${specifier} does not provide an export named ${name}`);
+          }
+          return module[name];
+        });
+      },
+
+      // thrown by the module loader when the host has no module `name`, reported to the host as
+      // ExecutionError::ModuleNotFound if the script does not catch it
+      moduleNotFound(name, importer) {
+        const by = importer === undefined ? "" : ` imported by ${JSON.stringify(importer)}`;
+        const error = new Error(`cannot find module ${JSON.stringify(name)}${by}`);
+        error.name = "ModuleNotFoundError";
+        return error;
+      },
+
+      // the default export of a script evaluated as a module, undefined if it has none
+      importDefault(specifier) {
+        return import(specifier).then((module) => module.default);
+      },
+      // the namespace of a script evaluated as a module, to call its exports
+      importNamespace(specifier) {
+        return import(specifier);
+      },
+
+      // values of CBOR tags and byte strings
+      date(time) {
+        return new Date(time);
+      },
+      bytes(buffer) {
+        return new Uint8Array(buffer);
+      },
+      // `[buffer, byteOffset, byteLength]` of a typed array or data view, undefined otherwise
+      view(value) {
+        if (!ArrayBuffer.isView(value)) {
+          return undefined;
+        }
+        const getters = value instanceof DataView ? dataViewGetters : typedArrayGetters;
+        return getters.map((get) => Reflect.apply(get, value, []));
+      },
+      // an integer outside the safe integer range of a number from its decimal digits
+      bigint(digits) {
+        return BigInt(digits);
+      },
+
+      freeze(value) {
+        return Object.freeze(value);
+      },
+      typeError(message) {
+        return new TypeError(message);
+      },
+
+      // a read-only view of data resolved by the host one path at a time with `get(path)`,
+      // which returns `{ value }`, `{ object: keys }`, `{ array: length }` or null if missing
+      lazy(get) {
+        const readOnly = () => {
+          throw new TypeError("data is read-only");
+        };
+        const view = (path, node) => {
+          if (node === null) {
+            return undefined;
+          }
+          if ("value" in node) {
+            return node.value;
+          }
+
+          const array = "array" in node;
+          const keys = array ? Array.from({ length: node.array }, (_, i) => String(i)) : node.object;
+          const own = new Set(keys);
+          const cache = new Map();
+          const read = (key) => {
+            if (!cache.has(key)) {
+              const child = [...path, array ? Number(key) : key];
+              cache.set(key, view(child, get(child)));
+            }
+            return cache.get(key);
+          };
+
+          return new Proxy(array ? [] : {}, {
+            get(target, key, receiver) {
+              if (array && key === "length") {
+                return node.array;
+              }
+              return own.has(key) ? read(key) : Reflect.get(target, key, receiver);
+            },
+            has(target, key) {
+              return own.has(key) || key in target;
+            },
+            ownKeys() {
+              return array ? [...keys, "length"] : [...keys];
+            },
+            getOwnPropertyDescriptor(target, key) {
+              if (array && key === "length") {
+                return { value: node.array, writable: true, enumerable: false, configurable: false };
+              }
+              // an accessor so listing the keys (i.e. `Object.keys` or `for...in`) does not
+              // resolve every value
+              if (own.has(key)) {
+                return { get: () => read(key), set: undefined, enumerable: true, configurable: true };
+              }
+              return undefined;
+            },
+            set: readOnly,
+            deleteProperty: readOnly,
+            defineProperty: readOnly,
+            setPrototypeOf: readOnly,
+          });
+        };
+        return view([], get([]));
+      },
+
+      // track the outcome of a promise (or any thenable) once the event loop finished
+      settle(value) {
+        const state = { settled: false, rejected: false };
+        Promise.resolve(value).then(
+          (value) => {
+            state.settled = true;
+            state.value = value;
+          },
+          (error) => {
+            state.settled = true;
+            state.rejected = true;
+            // formatted like an uncaught exception so the host can parse it
+            state.error =
+              error instanceof Error
+                ? `${error.name}: ${error.message}\n${error.stack ?? ""}`
+                : `Error: ${String(error)}`;
+          },
+        );
+        return state;
+      },
+    }),
+  });
+})();
@@ -0,0 +1,31 @@
+use crate::event_loop;
+use anyhow::{bail, Result};
+use quickjs_wasm_rs::{JSContextRef, JSValueRef};
+
+/// Runs the event loop until no jobs or timers remain and unwraps `value` if it is a promise.
+///
+/// A rejection is returned as an uncaught exception. The host time limit still applies while
+/// the event loop runs.
+pub fn settle<'a>(context: &'a JSContextRef, value: JSValueRef<'a>) -> Result<JSValueRef<'a>> {
+    if !is_thenable(&value)? {
+        event_loop::run(context)?;
+        return Ok(value);
+    }
+
+    let helpers = context.global_object()?.get_property("__quickjs")?;
+    let state = helpers.get_property("settle")?.call(&helpers, &[value])?;
+
+    event_loop::run(context)?;
+
+    if !state.get_property("settled")?.as_bool()? {
+        bail!("Uncaught Error: the promise returned by the script never settled");
+    }
+    if state.get_property("rejected")?.as_bool()? {
+        bail!("Uncaught {}", state.get_property("error")?);
+    }
+    state.get_property("value")
+}
+
+fn is_thenable(value: &JSValueRef) -> Result<bool> {
+    Ok(value.is_object() && value.get_property("then")?.is_function())
+}
@@ -7,57 +7,50 @@ pub fn bench(c: &mut Criterion) {
     let script = include_str!("../../../track_points.js");
     let data = include_str!("../../../track_points.json");
 
-    let quickjs = QuickJS::try_new(None, false, false, None, None).unwrap();
+    let quickjs = QuickJS::builder().build().unwrap();
     c.bench_function("try_execute", |b| {
         b.iter(|| black_box(quickjs.try_execute(script, Some(data)).unwrap()))
     });
 
-    let quickjs = QuickJS::try_new(None, false, false, Some(4194304), None).unwrap();
+    // dominated by the per call setup i.e. instantiation
+    c.bench_function("try_execute_trivial", |b| {
+        b.iter(|| black_box(quickjs.try_execute("1 + 1", None).unwrap()))
+    });
+
+    let quickjs = QuickJS::builder().memory_limit(4194304).build().unwrap();
     c.bench_function("try_execute_with_memory_limit", |b| {
         b.iter(|| black_box(quickjs.try_execute(script, Some(data)).unwrap()))
     });
 
-    let quickjs = QuickJS::try_new(
-        None,
-        false,
-        false,
-        None,
-        Some(
+    let quickjs = QuickJS::builder()
+        .time_limit(
             TimeLimit::new(Duration::from_millis(10000))
                 .with_evaluation_interval(Duration::from_micros(100)),
-        ),
-    )
-    .unwrap();
+        )
+        .build()
+        .unwrap();
     c.bench_function("try_execute_with_time_limit_100us", |b| {
         b.iter(|| black_box(quickjs.try_execute(script, Some(data)).unwrap()))
     });
 
-    let quickjs = QuickJS::try_new(
-        None,
-        false,
-        false,
-        None,
-        Some(
+    let quickjs = QuickJS::builder()
+        .time_limit(
             TimeLimit::new(Duration::from_millis(10000))
                 .with_evaluation_interval(Duration::from_micros(1000)),
-        ),
-    )
-    .unwrap();
+        )
+        .build()
+        .unwrap();
     c.bench_function("try_execute_with_time_limit_1000us", |b| {
         b.iter(|| black_box(quickjs.try_execute(script, Some(data)).unwrap()))
     });
 
-    let quickjs = QuickJS::try_new(
-        None,
-        false,
-        false,
-        None,
-        Some(
+    let quickjs = QuickJS::builder()
+        .time_limit(
             TimeLimit::new(Duration::from_millis(10000))
                 .with_evaluation_interval(Duration::from_micros(10000)),
-        ),
-    )
-    .unwrap();
+        )
+        .build()
+        .unwrap();
     c.bench_function("try_execute_with_time_limit_10000us", |b| {
         b.iter(|| black_box(quickjs.try_execute(script, Some(data)).unwrap()))
     });
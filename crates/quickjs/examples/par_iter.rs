@@ -2,7 +2,7 @@ extern crate quickjs;
 
 use anyhow::Result;
 use clap::Parser;
-use quickjs::{QuickJS, TimeLimit};
+use quickjs::{QuickJS, Stdio, TimeLimit};
 use rayon::prelude::*;
 use std::{
     path::PathBuf,
@@ -53,19 +53,31 @@ struct Args {
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    let quickjs = QuickJS::try_new(
-        args.module,
-        args.inherit_stdout,
-        args.inherit_stderr,
-        args.memory_limit_bytes,
-        args.time_limit_micros.map(|limit| {
-            let mut limit = TimeLimit::new(Duration::from_micros(limit));
-            if let Some(evaluation_interval) = args.time_limit_evaluation_interval_micros {
-                limit.evaluation_interval = Duration::from_micros(evaluation_interval);
-            }
-            limit
-        }),
-    )?;
+    let mut builder = QuickJS::builder()
+        .stdout(if args.inherit_stdout {
+            Stdio::Inherit
+        } else {
+            Stdio::Null
+        })
+        .stderr(if args.inherit_stderr {
+            Stdio::Inherit
+        } else {
+            Stdio::Null
+        });
+    if let Some(module) = args.module {
+        builder = builder.module_path(module);
+    }
+    if let Some(memory_limit) = args.memory_limit_bytes {
+        builder = builder.memory_limit(memory_limit);
+    }
+    if let Some(limit) = args.time_limit_micros {
+        let mut limit = TimeLimit::new(Duration::from_micros(limit));
+        if let Some(evaluation_interval) = args.time_limit_evaluation_interval_micros {
+            limit = limit.with_evaluation_interval(Duration::from_micros(evaluation_interval));
+        }
+        builder = builder.time_limit(limit);
+    }
+    let quickjs = builder.build()?;
 
     let script = std::fs::read_to_string(args.script)?;
     let data = std::fs::read_to_string(args.data)?;
@@ -0,0 +1,375 @@
+use crate::{
+    cache::ModuleCache, host::HostFunctions, pool::Pool, FuelLimit, QuickJS, TimeLimit, TimerMode,
+    WireFormat, PAGE_SIZE,
+};
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{borrow::Cow, fs, path::PathBuf, sync::Arc};
+use wasmtime::{Config, Engine, Module, OptLevel};
+
+#[cfg(not(feature = "precompiled"))]
+static QUICKJS_WASM: &[u8] = include_bytes!("../../../quickjs.wasm");
+
+/// Where the quickjs wasm module is loaded from.
+#[derive(Clone, Debug, Default)]
+pub enum ModuleSource {
+    /// the `quickjs.wasm` embedded at compile time or `quickjs.cwasm` with the `precompiled`
+    /// feature
+    #[default]
+    Embedded,
+    /// a wasm module on disk
+    Path(PathBuf),
+    /// a wasm module already held in memory
+    Bytes(Vec<u8>),
+}
+
+/// What happens to a stream written by the guest (i.e. `console.log`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Stdio {
+    /// discard everything written to the stream
+    #[default]
+    Null,
+    /// forward the stream to the host process
+    Inherit,
+    /// capture the stream of each execution in memory, keeping at most `limit` bytes. reported
+    /// through [`ExecutionOptions::output`](crate::ExecutionOptions::output).
+    Capture { limit: usize },
+}
+
+/// Builder for [`QuickJS`].
+///
+/// ```no_run
+/// use quickjs::{QuickJS, Stdio, TimeLimit};
+/// use std::time::Duration;
+///
+/// let quickjs = QuickJS::builder()
+///     .stdout(Stdio::Inherit)
+///     .memory_limit(4194304)
+///     .time_limit(TimeLimit::new(Duration::from_secs(1)))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct QuickJSBuilder {
+    pub(crate) source: ModuleSource,
+    pub(crate) stdout: Stdio,
+    pub(crate) stderr: Stdio,
+    pub(crate) memory_limit: Option<u32>,
+    pub(crate) time_limit: Option<TimeLimit>,
+    pub(crate) fuel_limit: Option<FuelLimit>,
+    pub(crate) cancellable: bool,
+    pub(crate) async_support: bool,
+    pub(crate) timer_mode: TimerMode,
+    pub(crate) wire_format: WireFormat,
+    pub(crate) opt_level: OptLevel,
+    pub(crate) parallel_compilation: bool,
+    pub(crate) max_concurrency: Option<u32>,
+    pub(crate) cache_dir: Option<PathBuf>,
+    pub(crate) host_functions: HostFunctions,
+    #[cfg(feature = "wizer")]
+    pub(crate) specialize: Option<String>,
+}
+
+impl Default for QuickJSBuilder {
+    fn default() -> Self {
+        Self {
+            source: ModuleSource::default(),
+            stdout: Stdio::default(),
+            stderr: Stdio::default(),
+            memory_limit: None,
+            time_limit: None,
+            fuel_limit: None,
+            cancellable: false,
+            async_support: false,
+            timer_mode: TimerMode::default(),
+            wire_format: WireFormat::default(),
+            opt_level: OptLevel::Speed,
+            parallel_compilation: true,
+            max_concurrency: None,
+            cache_dir: None,
+            host_functions: HostFunctions::default(),
+            #[cfg(feature = "wizer")]
+            specialize: None,
+        }
+    }
+}
+
+impl QuickJSBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// load the quickjs wasm module from `source` instead of the embedded `quickjs.wasm`
+    pub fn module(mut self, source: ModuleSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// load the quickjs wasm module from a file
+    pub fn module_path(self, path: impl Into<PathBuf>) -> Self {
+        self.module(ModuleSource::Path(path.into()))
+    }
+
+    /// load the quickjs wasm module from bytes
+    pub fn module_bytes(self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.module(ModuleSource::Bytes(bytes.into()))
+    }
+
+    /// route `console.log` calls
+    pub fn stdout(mut self, stdout: Stdio) -> Self {
+        self.stdout = stdout;
+        self
+    }
+
+    /// route `console.error` calls
+    pub fn stderr(mut self, stderr: Stdio) -> Self {
+        self.stderr = stderr;
+        self
+    }
+
+    /// runtime memory limit in bytes to restrict unconstrained memory growth
+    pub fn memory_limit(mut self, memory_limit: u32) -> Self {
+        self.memory_limit = Some(memory_limit);
+        self
+    }
+
+    /// runtime time limit to restrict long running programs/infinite loops
+    pub fn time_limit(mut self, time_limit: TimeLimit) -> Self {
+        self.time_limit = Some(time_limit);
+        self
+    }
+
+    /// deterministic instruction budget for each execution. can be combined with a time limit.
+    pub fn fuel_limit(mut self, fuel_limit: FuelLimit) -> Self {
+        self.fuel_limit = Some(fuel_limit);
+        self
+    }
+
+    /// allow executions to be aborted from another thread with an
+    /// [`ExecutionHandle`](crate::ExecutionHandle). enables epoch interruption which adds a small
+    /// overhead to every execution.
+    pub fn cancellable(mut self, cancellable: bool) -> Self {
+        self.cancellable = cancellable;
+        self
+    }
+
+    /// run executions with [`QuickJS::try_execute_async`] on an async executor, yielding to it
+    /// periodically. the blocking methods fail when enabled.
+    pub fn async_support(mut self, async_support: bool) -> Self {
+        self.async_support = async_support;
+        self
+    }
+
+    /// how time passes for `setTimeout` and `setInterval`. default `TimerMode::RealTime`
+    pub fn timers(mut self, timer_mode: TimerMode) -> Self {
+        self.timer_mode = timer_mode;
+        self
+    }
+
+    /// encoding of `data` and results for the typed methods and
+    /// [`QuickJS::try_execute_bytes`]. default `WireFormat::Json`
+    pub fn wire_format(mut self, wire_format: WireFormat) -> Self {
+        self.wire_format = wire_format;
+        self
+    }
+
+    /// cranelift optimization level used to compile the module. default `OptLevel::Speed`
+    pub fn cranelift_opt_level(mut self, opt_level: OptLevel) -> Self {
+        self.opt_level = opt_level;
+        self
+    }
+
+    /// compile the module on multiple threads. default `true`
+    pub fn parallel_compilation(mut self, parallel_compilation: bool) -> Self {
+        self.parallel_compilation = parallel_compilation;
+        self
+    }
+
+    /// allocate instances from a pool of `max_concurrency` preallocated slots sized to the
+    /// memory limit. slots are reused between executions and an execution fails immediately with
+    /// [`ExecutionError::PoolExhausted`](crate::ExecutionError::PoolExhausted) when all are in use.
+    ///
+    /// requires a `memory_limit`.
+    pub fn pooling_allocator(mut self, max_concurrency: u32) -> Self {
+        self.max_concurrency = Some(max_concurrency);
+        self
+    }
+
+    /// persist the compiled module in `cache_dir` and reuse it on the next start instead of
+    /// compiling it again. artifacts are keyed by the wasm bytes and the engine configuration.
+    pub fn cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    /// register a Rust function callable from javascript as `host.<name>(...args)`
+    ///
+    /// the javascript arguments are passed as a JSON array and the returned value becomes the
+    /// result of the call. an error is thrown as an exception that the script can catch. `name`
+    /// must be an identifier that is not reserved, like the names of [`Inputs`](crate::Inputs),
+    /// otherwise `build` fails.
+    ///
+    /// ```no_run
+    /// use quickjs::QuickJS;
+    /// use serde_json::{json, Value};
+    ///
+    /// let quickjs = QuickJS::builder()
+    ///     .host_fn("lookupUser", |args: Value| Ok(json!({ "id": args[0] })))
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// quickjs.try_execute("host.lookupUser(42).id", None).unwrap();
+    /// ```
+    pub fn host_fn<F>(mut self, name: impl Into<String>, function: F) -> Self
+    where
+        F: Fn(Value) -> Result<Value> + Send + Sync + 'static,
+    {
+        self.host_functions.insert(name.into(), Arc::new(function));
+        self
+    }
+
+    /// run `script` once while building and snapshot the initialized module with wizer, so every
+    /// execution starts with its top-level state (i.e. library code or lookup tables) already
+    /// built. later executions, i.e. [`QuickJS::call`], can use everything it defines globally.
+    ///
+    /// an exception thrown by `script` fails the build with
+    /// [`ExecutionError::JsException`](crate::ExecutionError::JsException). host functions and
+    /// timers are unavailable while it runs.
+    #[cfg(feature = "wizer")]
+    pub fn specialize(mut self, script: impl Into<String>) -> Self {
+        self.specialize = Some(script.into());
+        self
+    }
+
+    fn validate(&self) -> Result<()> {
+        if let Some(memory_limit) = self.memory_limit {
+            if memory_limit < PAGE_SIZE {
+                bail!(
+                    "memory limit of {memory_limit} bytes is smaller than one wasm page ({PAGE_SIZE} bytes)"
+                );
+            }
+        }
+
+        if let Some(time_limit) = &self.time_limit {
+            if time_limit.evaluation_interval.is_zero() {
+                bail!("time limit evaluation_interval must be greater than zero");
+            }
+            if time_limit.limit < time_limit.evaluation_interval {
+                bail!(
+                    "time limit of {:?} is shorter than its evaluation_interval of {:?}",
+                    time_limit.limit,
+                    time_limit.evaluation_interval
+                );
+            }
+        }
+
+        if let Some(fuel_limit) = &self.fuel_limit {
+            if fuel_limit.fuel == 0 {
+                bail!("fuel limit must be greater than zero");
+            }
+        }
+
+        self.host_functions.validate()?;
+
+        if let Some(max_concurrency) = self.max_concurrency {
+            if max_concurrency == 0 {
+                bail!("pooling allocator max_concurrency must be greater than zero");
+            }
+            if self.memory_limit.is_none() {
+                bail!("pooling allocator requires a memory limit to size its slots");
+            }
+        }
+
+        // the precompiled module is compiled for an engine without fuel metering or async support
+        #[cfg(feature = "precompiled")]
+        if matches!(self.source, ModuleSource::Embedded) {
+            if self.fuel_limit.is_some() {
+                bail!("the precompiled module is compiled without fuel metering, use a wasm module for a fuel limit");
+            }
+            if self.async_support {
+                bail!(
+                    "the precompiled module is compiled without async support, use a wasm module"
+                );
+            }
+        }
+
+        #[cfg(all(feature = "wizer", feature = "precompiled"))]
+        if self.specialize.is_some() && matches!(self.source, ModuleSource::Embedded) {
+            bail!("the precompiled module cannot be specialized, use a wasm module");
+        }
+
+        if let ModuleSource::Bytes(bytes) = &self.source {
+            if bytes.is_empty() {
+                bail!("module bytes are empty");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// validate the configuration and compile the module
+    pub fn build(self) -> Result<QuickJS> {
+        self.validate()?;
+
+        let mut config = Config::new();
+        config
+            // the embedded precompiled module is always compiled with epoch interruption
+            .epoch_interruption(
+                self.time_limit.is_some()
+                    || self.cancellable
+                    || self.async_support
+                    || (cfg!(feature = "precompiled")
+                        && matches!(self.source, ModuleSource::Embedded)),
+            )
+            .async_support(self.async_support)
+            .consume_fuel(self.fuel_limit.is_some())
+            .cranelift_opt_level(self.opt_level)
+            .parallel_compilation(self.parallel_compilation);
+        let pool = match (self.max_concurrency, self.memory_limit) {
+            (Some(max_concurrency), Some(memory_limit)) => {
+                Some(Pool::configure(&mut config, max_concurrency, memory_limit))
+            }
+            _ => None,
+        };
+        let engine = Engine::new(&config)?;
+
+        let (module, module_hash) = self.load_module(&engine)?;
+
+        QuickJS::from_parts(engine, module, module_hash, pool, self)
+    }
+
+    /// load the module and the sha256 of the bytes it was compiled from
+    fn load_module(&self, engine: &Engine) -> Result<(Module, [u8; 32])> {
+        let wasm: Cow<[u8]> = match &self.source {
+            #[cfg(feature = "precompiled")]
+            ModuleSource::Embedded => {
+                let cwasm = include_bytes!("../../../quickjs.cwasm");
+                // SAFETY: quickjs.cwasm is produced by `make build_cwasm` with wasmtime which
+                // validates the artifact matches this engine before loading it
+                let module = unsafe { Module::deserialize(engine, cwasm) }
+                    .context("failed to load precompiled quickjs.cwasm, it must be compiled by the same wasmtime version with settings matching the engine")?;
+                return Ok((module, Sha256::digest(cwasm).into()));
+            }
+            #[cfg(not(feature = "precompiled"))]
+            ModuleSource::Embedded => Cow::Borrowed(QUICKJS_WASM),
+            ModuleSource::Path(path) => Cow::Owned(
+                fs::read(path)
+                    .with_context(|| format!("failed to load module from {}", path.display()))?,
+            ),
+            ModuleSource::Bytes(bytes) => Cow::Borrowed(bytes),
+        };
+
+        #[cfg(feature = "wizer")]
+        let wasm = match &self.specialize {
+            Some(script) => Cow::Owned(crate::specialize::specialize(&wasm, script)?),
+            None => wasm,
+        };
+
+        let module = match &self.cache_dir {
+            Some(cache_dir) => ModuleCache::new(cache_dir).load_or_compile(engine, &wasm)?,
+            None => Module::from_binary(engine, &wasm)?,
+        };
+        Ok((module, Sha256::digest(&wasm).into()))
+    }
+}
@@ -0,0 +1,94 @@
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use wasmtime::{Engine, Module};
+
+/// On-disk cache of compiled modules keyed by the wasm bytes and the engine configuration.
+#[derive(Debug)]
+pub(crate) struct ModuleCache<'a> {
+    dir: &'a Path,
+}
+
+/// Adapts [`Sha256`] to [`Hasher`] so [`Engine::precompile_compatibility_hash`] can be folded
+/// into the cache key.
+struct Sha256Hasher(Sha256);
+
+impl Hasher for Sha256Hasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        let digest = self.0.clone().finalize();
+        u64::from_le_bytes(digest[..8].try_into().unwrap())
+    }
+}
+
+impl<'a> ModuleCache<'a> {
+    pub fn new(dir: &'a Path) -> Self {
+        Self { dir }
+    }
+
+    /// load a previously compiled module for `wasm` or compile and persist it. the compiled module
+    /// is still returned if it cannot be persisted.
+    pub fn load_or_compile(&self, engine: &Engine, wasm: &[u8]) -> Result<Module> {
+        let path = self.path(engine, wasm);
+
+        if path.exists() {
+            // SAFETY: the artifact was written by `Module::serialize` for the same wasm and a
+            // compatible engine as both are part of its file name
+            match unsafe { Module::deserialize_file(engine, &path) } {
+                Ok(module) => return Ok(module),
+                // corrupt or written by an incompatible wasmtime, recompile and replace it
+                Err(_) => fs::remove_file(&path).ok(),
+            };
+        }
+
+        let module = Module::from_binary(engine, wasm)?;
+        // the cache only saves compiling the module again
+        self.persist(&path, &module).ok();
+        Ok(module)
+    }
+
+    fn path(&self, engine: &Engine, wasm: &[u8]) -> PathBuf {
+        let mut hasher = Sha256Hasher(Sha256::new());
+        hasher.write(wasm);
+        engine.precompile_compatibility_hash().hash(&mut hasher);
+
+        let key = hasher
+            .0
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        self.dir.join(format!("{key}.cwasm"))
+    }
+
+    fn persist(&self, path: &Path, module: &Module) -> Result<()> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        fs::create_dir_all(self.dir)?;
+
+        // write to a temporary file unique to this call first so concurrent builds never read or
+        // write a partial artifact
+        let tmp = path.with_extension(format!(
+            "{}-{}.tmp",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = module
+            .serialize()
+            .and_then(|bytes| Ok(fs::write(&tmp, bytes)?))
+            .and_then(|()| Ok(fs::rename(&tmp, path)?));
+        if result.is_err() {
+            fs::remove_file(&tmp).ok();
+        }
+        result
+    }
+}
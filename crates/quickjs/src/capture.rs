@@ -0,0 +1,41 @@
+use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+/// An in-memory stream that keeps at most `limit` bytes written by the guest and silently
+/// discards the rest.
+#[derive(Clone, Debug)]
+pub(crate) struct CaptureBuffer {
+    buffer: Arc<Mutex<Vec<u8>>>,
+    limit: usize,
+}
+
+impl CaptureBuffer {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            buffer: Arc::default(),
+            limit,
+        }
+    }
+
+    /// take everything captured so far
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.buffer.lock().unwrap())
+    }
+}
+
+impl Write for CaptureBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut buffer = self.buffer.lock().unwrap();
+        let remaining = self.limit.saturating_sub(buffer.len());
+        buffer.extend_from_slice(&buf[..buf.len().min(remaining)]);
+
+        // report everything as written so the guest does not retry or fail
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
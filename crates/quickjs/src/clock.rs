@@ -0,0 +1,89 @@
+use crate::{ExecutionError, ExecutionHandle};
+use anyhow::{bail, Result};
+use std::time::{Duration, Instant};
+
+/// the longest a cancellable execution sleeps for a timer before checking its handle
+static CANCEL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How time passes for `setTimeout` and `setInterval` in the guest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimerMode {
+    /// timers fire after their delay has elapsed on the wall clock
+    #[default]
+    RealTime,
+    /// time jumps forward to the next timer instead of waiting for it, i.e. for tests
+    Virtual,
+}
+
+/// The clock of a single execution read by the guest event loop in milliseconds since it
+/// started.
+pub(crate) struct Clock {
+    mode: TimerMode,
+    started: Instant,
+    elapsed: f64,
+    deadline: Option<Instant>,
+    handle: Option<ExecutionHandle>,
+}
+
+impl Clock {
+    /// `deadline` is when the time limit of the execution elapses and `handle` cancels waiting
+    /// for a timer
+    pub fn new(
+        mode: TimerMode,
+        deadline: Option<Instant>,
+        handle: Option<ExecutionHandle>,
+    ) -> Self {
+        Self {
+            mode,
+            started: Instant::now(),
+            elapsed: 0.0,
+            deadline,
+            handle,
+        }
+    }
+
+    pub fn now(&self) -> f64 {
+        match self.mode {
+            TimerMode::RealTime => self.started.elapsed().as_secs_f64() * 1000.0,
+            TimerMode::Virtual => self.elapsed,
+        }
+    }
+
+    /// advance to `due` returning when the caller has to wake up for it in real time.
+    /// fails without waiting if `due` is after the time limit.
+    pub fn wait_until(&mut self, due: f64) -> Result<Instant> {
+        match self.mode {
+            TimerMode::RealTime => {
+                let wake = self.started + Duration::from_secs_f64(due.max(0.0) / 1000.0);
+                if self.deadline.is_some_and(|deadline| wake > deadline) {
+                    bail!(ExecutionError::Timeout);
+                }
+                Ok(wake)
+            }
+            TimerMode::Virtual => {
+                self.elapsed = self.elapsed.max(due);
+                Ok(Instant::now())
+            }
+        }
+    }
+
+    /// how long to sleep before calling again until `wake`, or none once it passed. a cancellable
+    /// execution sleeps in short slices and fails with [`ExecutionError::Cancelled`] once
+    /// cancelled.
+    pub fn next_sleep(&self, wake: Instant) -> Result<Option<Duration>> {
+        if self
+            .handle
+            .as_ref()
+            .is_some_and(ExecutionHandle::is_cancelled)
+        {
+            bail!(ExecutionError::Cancelled);
+        }
+
+        let remaining = wake.saturating_duration_since(Instant::now());
+        Ok(match (remaining.is_zero(), &self.handle) {
+            (true, _) => None,
+            (false, Some(_)) => Some(remaining.min(CANCEL_INTERVAL)),
+            (false, None) => Some(remaining),
+        })
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+/// QuickJS bytecode of a script produced by [`QuickJS::compile`](crate::QuickJS::compile).
+///
+/// Executing it with [`QuickJS::try_execute_compiled`](crate::QuickJS::try_execute_compiled)
+/// skips parsing the script. Clones share the bytecode so it can be compiled once and used from
+/// many threads. The bytecode is only valid for the quickjs module that compiled it, executing it
+/// with another module fails with [`ExecutionError::InvalidInput`](crate::ExecutionError).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompiledScript {
+    name: Arc<str>,
+    bytecode: Arc<[u8]>,
+    module_hash: [u8; 32],
+}
+
+impl CompiledScript {
+    pub(crate) fn new(name: &str, bytecode: Vec<u8>, module_hash: [u8; 32]) -> Self {
+        Self {
+            name: name.into(),
+            bytecode: bytecode.into(),
+            module_hash,
+        }
+    }
+
+    /// the name the script is reported as in exception stacks
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn bytecode(&self) -> &[u8] {
+        &self.bytecode
+    }
+
+    /// the sha256 of the module that compiled the bytecode
+    pub(crate) fn module_hash(&self) -> [u8; 32] {
+        self.module_hash
+    }
+}
@@ -0,0 +1,136 @@
+use serde::Deserialize;
+use std::{error::Error, fmt, string::FromUtf8Error};
+use wasmtime::Trap;
+
+/// The reason an execution failed.
+#[derive(Debug)]
+pub enum ExecutionError {
+    /// the script threw an uncaught exception
+    JsException {
+        name: String,
+        message: String,
+        stack: Option<String>,
+    },
+    /// the function requested by [`QuickJS::call`](crate::QuickJS::call) does not exist
+    FunctionNotFound(String),
+    /// the value requested by [`QuickJS::call`](crate::QuickJS::call) is not a function
+    NotCallable(String),
+    /// the [`ModuleResolver`](crate::ModuleResolver) has no source for the imported module
+    ModuleNotFound(String),
+    /// the execution exceeded its [`TimeLimit`](crate::TimeLimit)
+    Timeout,
+    /// the execution was aborted with [`ExecutionHandle::cancel`](crate::ExecutionHandle::cancel)
+    Cancelled,
+    /// the execution consumed all fuel of its [`FuelLimit`](crate::FuelLimit)
+    OutOfFuel,
+    /// the guest exhausted its memory limit
+    OutOfMemory,
+    /// every slot of the pooling allocator is in use
+    PoolExhausted { max_concurrency: u32 },
+    /// the wasm guest trapped
+    Trap(Trap),
+    /// the input could not be encoded
+    InvalidInput(Box<dyn Error + Send + Sync>),
+    /// the output produced by the guest could not be decoded
+    InvalidOutput(Box<dyn Error + Send + Sync>),
+    /// the host failed to set up or run the execution, i.e. linking or instantiation
+    Host(anyhow::Error),
+}
+
+/// An exception as serialized by the guest.
+#[derive(Deserialize)]
+struct JsException {
+    name: String,
+    message: String,
+    stack: Option<String>,
+}
+
+impl ExecutionError {
+    /// decode an exception payload written by the guest with `set_output`
+    pub(crate) fn from_exception(bytes: &[u8]) -> Self {
+        let exception =
+            serde_json::from_slice::<JsException>(bytes).unwrap_or_else(|_| JsException {
+                name: "Error".to_string(),
+                message: String::from_utf8_lossy(bytes).into_owned(),
+                stack: None,
+            });
+
+        // quickjs raises an InternalError when its allocator fails
+        if exception.name == "InternalError" && exception.message == "out of memory" {
+            return Self::OutOfMemory;
+        }
+        // thrown by the module loader of the guest at an import the resolver has no source for
+        if exception.name == "ModuleNotFoundError" {
+            if let Some(name) = missing_module(&exception.message) {
+                return Self::ModuleNotFound(name);
+            }
+        }
+
+        Self::JsException {
+            name: exception.name,
+            message: exception.message,
+            stack: exception.stack,
+        }
+    }
+}
+
+/// the module named by the message of a `ModuleNotFoundError`, i.e.
+/// `cannot find module "lib/missing.js" imported by "main.js"`
+fn missing_module(message: &str) -> Option<String> {
+    let quoted = message.strip_prefix("cannot find module ")?;
+    serde_json::Deserializer::from_str(quoted)
+        .into_iter::<String>()
+        .next()?
+        .ok()
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::JsException { name, message, .. } => write!(f, "Uncaught {name}: {message}"),
+            Self::FunctionNotFound(name) => write!(f, "function {name} is not defined"),
+            Self::NotCallable(name) => write!(f, "{name} is not a function"),
+            Self::ModuleNotFound(name) => write!(f, "cannot find module {name}"),
+            Self::Timeout => write!(f, "exceeds time limit"),
+            Self::Cancelled => write!(f, "execution cancelled"),
+            Self::OutOfFuel => write!(f, "exceeds fuel limit"),
+            Self::OutOfMemory => write!(f, "out of memory"),
+            Self::PoolExhausted { max_concurrency } => {
+                write!(f, "all {max_concurrency} instance slots are in use")
+            }
+            Self::Trap(trap) => write!(f, "wasm trap: {trap}"),
+            Self::InvalidInput(err) => write!(f, "invalid input: {err}"),
+            Self::InvalidOutput(err) => write!(f, "invalid output: {err}"),
+            Self::Host(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error for ExecutionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::InvalidInput(err) | Self::InvalidOutput(err) => Some(&**err),
+            Self::Host(err) => Some(&**err),
+            _ => None,
+        }
+    }
+}
+
+impl From<anyhow::Error> for ExecutionError {
+    /// classify an error raised while running the guest
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<ExecutionError>() {
+            Ok(err) => return err,
+            Err(err) => err,
+        };
+        match err.downcast_ref::<Trap>() {
+            Some(Trap::OutOfFuel) => return Self::OutOfFuel,
+            Some(trap) => return Self::Trap(*trap),
+            None => {}
+        }
+        match err.downcast::<FromUtf8Error>() {
+            Ok(err) => Self::InvalidOutput(Box::new(err)),
+            Err(err) => Self::Host(err),
+        }
+    }
+}
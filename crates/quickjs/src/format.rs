@@ -0,0 +1,61 @@
+use crate::ExecutionError;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// How `data` and the result of an execution are encoded between the host and the guest.
+///
+/// Used by the typed methods (i.e. [`QuickJS::try_execute_typed`](crate::QuickJS::try_execute_typed)
+/// and [`QuickJS::call`](crate::QuickJS::call)) and the raw bytes of
+/// [`QuickJS::try_execute_bytes`](crate::QuickJS::try_execute_bytes). The methods taking and
+/// returning strings always use JSON.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(i32)]
+pub enum WireFormat {
+    /// JSON text
+    #[default]
+    Json = 0,
+    /// MessagePack, smaller and faster to encode for large or numeric inputs. structs are
+    /// encoded as maps so they become objects in javascript.
+    MessagePack = 1,
+    /// CBOR, mapping byte strings to `Uint8Array` and dates (tags 0 and 1) to `Date` so both
+    /// round-trip without loss. a `Date` is returned with tag 1.
+    Cbor = 2,
+}
+
+impl WireFormat {
+    pub(crate) fn serialize<T: Serialize + ?Sized>(
+        self,
+        value: &T,
+    ) -> Result<Vec<u8>, ExecutionError> {
+        match self {
+            Self::Json => {
+                serde_json::to_vec(value).map_err(|err| ExecutionError::InvalidInput(Box::new(err)))
+            }
+            Self::MessagePack => rmp_serde::to_vec_named(value)
+                .map_err(|err| ExecutionError::InvalidInput(Box::new(err))),
+            Self::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes)
+                    .map_err(|err| ExecutionError::InvalidInput(Box::new(err)))?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// deserialize directly from the bytes read from guest memory. a script without a result
+    /// (i.e. `undefined`) deserializes from `null`.
+    pub(crate) fn deserialize<T: DeserializeOwned>(
+        self,
+        output: Option<Vec<u8>>,
+    ) -> Result<T, ExecutionError> {
+        match self {
+            Self::Json => serde_json::from_slice(output.as_deref().unwrap_or(b"null"))
+                .map_err(|err| ExecutionError::InvalidOutput(Box::new(err))),
+            // 0xc0 is nil
+            Self::MessagePack => rmp_serde::from_slice(output.as_deref().unwrap_or(&[0xc0]))
+                .map_err(|err| ExecutionError::InvalidOutput(Box::new(err))),
+            // 0xf6 is null
+            Self::Cbor => ciborium::from_reader(output.as_deref().unwrap_or(&[0xf6]))
+                .map_err(|err| ExecutionError::InvalidOutput(Box::new(err))),
+        }
+    }
+}
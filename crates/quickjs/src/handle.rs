@@ -0,0 +1,49 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use wasmtime::Engine;
+
+/// Aborts an in-flight execution from any thread.
+///
+/// Created by [`QuickJS::execution_handle`](crate::QuickJS::execution_handle) and passed with
+/// [`ExecutionOptions::handle`](crate::ExecutionOptions::handle). Clones share the same
+/// cancellation state.
+#[derive(Clone)]
+pub struct ExecutionHandle {
+    cancelled: Arc<AtomicBool>,
+    engine: Engine,
+}
+
+impl ExecutionHandle {
+    pub(crate) fn new(engine: Engine) -> Self {
+        Self {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            engine,
+        }
+    }
+
+    /// trap the execution at its next epoch check with
+    /// [`ExecutionError::Cancelled`](crate::ExecutionError::Cancelled). an execution that has not
+    /// started yet fails immediately.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        // wake every store of the engine, the others see they are not cancelled and continue
+        self.engine.increment_epoch();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+impl fmt::Debug for ExecutionHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExecutionHandle")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
@@ -0,0 +1,55 @@
+use crate::inputs::validate_name;
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt, sync::Arc};
+
+/// A Rust closure callable from javascript as `host.<name>(...args)`.
+///
+/// The arguments are passed as a JSON array.
+pub(crate) type HostFn = Arc<dyn Fn(Value) -> Result<Value> + Send + Sync>;
+
+/// The host functions registered with [`QuickJSBuilder::host_fn`](crate::QuickJSBuilder::host_fn).
+#[derive(Clone, Default)]
+pub(crate) struct HostFunctions {
+    functions: BTreeMap<String, HostFn>,
+    names: Vec<u8>,
+}
+
+impl HostFunctions {
+    pub fn insert(&mut self, name: String, function: HostFn) {
+        self.functions.insert(name, function);
+        self.names =
+            serde_json::to_vec(&self.functions.keys().collect::<Vec<_>>()).unwrap_or_default();
+    }
+
+    /// fails if a name is not an identifier or is reserved, like the names of
+    /// [`Inputs`](crate::Inputs)
+    pub fn validate(&self) -> Result<()> {
+        for name in self.functions.keys() {
+            validate_name("host function", name)?;
+        }
+        Ok(())
+    }
+
+    /// the JSON encoded names passed to the guest to build the `host` namespace
+    pub fn names(&self) -> &[u8] {
+        &self.names
+    }
+
+    /// call the function `name` with JSON encoded `args` returning its JSON encoded result
+    pub fn call(&self, name: &str, args: &[u8]) -> Result<Vec<u8>> {
+        let function = self
+            .functions
+            .get(name)
+            .ok_or_else(|| anyhow!("host function {name} is not registered"))?;
+
+        let args = serde_json::from_slice(args)?;
+        Ok(serde_json::to_vec(&function(args)?)?)
+    }
+}
+
+impl fmt::Debug for HostFunctions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.functions.keys()).finish()
+    }
+}
@@ -0,0 +1,179 @@
+use crate::ExecutionError;
+use anyhow::anyhow;
+use serde::Serialize;
+use serde_json::Value;
+
+/// words that cannot be bound as a global, including the globals the guest relies on. builtins
+/// used by the guest are captured by its prelude so they can be shadowed
+static RESERVED: &[&str] = &[
+    // keywords
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "import",
+    "in",
+    "instanceof",
+    "new",
+    "null",
+    "return",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+    // reserved in strict mode
+    "implements",
+    "interface",
+    "let",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "static",
+    // immutable or special globals
+    "arguments",
+    "eval",
+    "globalThis",
+    "Infinity",
+    "NaN",
+    "undefined",
+    // sets the prototype instead of binding a property
+    "__proto__",
+    // used by quickjs-wasm
+    "__quickjs",
+    "console",
+    "host",
+    "setTimeout",
+    "setInterval",
+    "clearTimeout",
+    "clearInterval",
+];
+
+/// Named values bound as separate globals by
+/// [`QuickJS::try_execute_inputs`](crate::QuickJS::try_execute_inputs) or passed as the
+/// arguments of [`QuickJS::call_inputs`](crate::QuickJS::call_inputs) in insertion order.
+///
+/// ```no_run
+/// use quickjs::{Inputs, QuickJS};
+/// use serde_json::json;
+///
+/// let mut inputs = Inputs::new();
+/// inputs.insert("request", &json!({ "id": 42 })).unwrap();
+/// inputs.insert_frozen("config", &json!({ "limit": 10 })).unwrap();
+///
+/// let quickjs = QuickJS::builder().build().unwrap();
+/// quickjs.try_execute_inputs("request.id < config.limit", &inputs).unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Inputs {
+    names: Vec<InputName>,
+    values: Vec<Value>,
+}
+
+/// How an input is bound, passed to the guest in the order of the values.
+#[derive(Clone, Debug, Serialize)]
+struct InputName {
+    name: String,
+    frozen: bool,
+}
+
+impl Inputs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// bind `value` as the global `name`, replacing an input of the same name. fails with
+    /// [`ExecutionError::InvalidInput`] if `name` is not an identifier or is reserved.
+    pub fn insert<T: Serialize + ?Sized>(
+        &mut self,
+        name: impl Into<String>,
+        value: &T,
+    ) -> Result<&mut Self, ExecutionError> {
+        self.push(name.into(), value, false)
+    }
+
+    /// like [`Inputs::insert`] but `Object.freeze` the value before the script runs
+    pub fn insert_frozen<T: Serialize + ?Sized>(
+        &mut self,
+        name: impl Into<String>,
+        value: &T,
+    ) -> Result<&mut Self, ExecutionError> {
+        self.push(name.into(), value, true)
+    }
+
+    fn push<T: Serialize + ?Sized>(
+        &mut self,
+        name: String,
+        value: &T,
+        frozen: bool,
+    ) -> Result<&mut Self, ExecutionError> {
+        validate_name("input", &name)?;
+        let value = serde_json::to_value(value)
+            .map_err(|err| ExecutionError::InvalidInput(Box::new(err)))?;
+
+        match self.names.iter().position(|input| input.name == name) {
+            Some(index) => {
+                self.names[index].frozen = frozen;
+                self.values[index] = value;
+            }
+            None => {
+                self.names.push(InputName { name, frozen });
+                self.values.push(value);
+            }
+        }
+        Ok(self)
+    }
+
+    pub(crate) fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    pub(crate) fn names_json(&self) -> Result<Vec<u8>, ExecutionError> {
+        serde_json::to_vec(&self.names).map_err(|err| ExecutionError::InvalidInput(Box::new(err)))
+    }
+}
+
+/// a name is bound with `globalThis[name] = value` so it has to be an identifier scripts can
+/// reference. `kind` is what the name is reported as.
+pub(crate) fn validate_name(kind: &str, name: &str) -> Result<(), ExecutionError> {
+    let mut chars = name.chars();
+    let identifier = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if !identifier {
+        return Err(ExecutionError::InvalidInput(
+            anyhow!("{kind} name {name:?} is not an identifier").into(),
+        ));
+    }
+    if RESERVED.contains(&name) {
+        return Err(ExecutionError::InvalidInput(
+            anyhow!("{kind} name {name} is reserved").into(),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Resolves the values a script reads from a lazy `data`, see
+/// [`QuickJS::try_execute_lazy`](crate::QuickJS::try_execute_lazy).
+///
+/// Called once for every object or array a script walks into and every value it reads, so only
+/// the values actually touched are copied into the guest. Implemented for [`Value`].
+pub trait DataProvider: Send + Sync {
+    /// what is at `path` from the root of `data` or `None` if it does not exist
+    fn get(&self, path: &[PathSegment]) -> Option<LazyValue>;
+}
+
+/// One step of the path to a value read by a script, i.e. `data.items[0]` is
+/// `[Key("items"), Index(0)]`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// A value at a path of a lazy `data`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LazyValue {
+    /// a value copied into the guest as a whole, objects and arrays included
+    Value(Value),
+    /// an object with these keys whose values are resolved when read
+    Object(Vec<String>),
+    /// an array of this length whose elements are resolved when read
+    Array(usize),
+}
+
+impl LazyValue {
+    /// `value` with its objects and arrays resolved lazily
+    pub fn shallow(value: &Value) -> Self {
+        match value {
+            Value::Object(map) => Self::Object(map.keys().cloned().collect()),
+            Value::Array(values) => Self::Array(values.len()),
+            value => Self::Value(value.clone()),
+        }
+    }
+}
+
+impl DataProvider for Value {
+    fn get(&self, path: &[PathSegment]) -> Option<LazyValue> {
+        let mut value = self;
+        for segment in path {
+            value = match (segment, value) {
+                (PathSegment::Key(key), Value::Object(map)) => map.get(key)?,
+                (PathSegment::Index(index), Value::Array(values)) => values.get(*index)?,
+                _ => return None,
+            };
+        }
+        Some(LazyValue::shallow(value))
+    }
+}
@@ -1,25 +1,81 @@
+mod builder;
+mod cache;
+mod capture;
+mod clock;
+mod compiled;
+mod error;
+mod format;
+mod handle;
+mod host;
+mod inputs;
+mod lazy;
+mod modules;
+mod options;
+mod pool;
+mod session;
+mod snapshot;
+#[cfg(feature = "wizer")]
+mod specialize;
+mod ticker;
+
 use anyhow::{anyhow, bail, Result};
+use capture::CaptureBuffer;
+use clock::Clock;
+use futures_timer::Delay;
+use host::HostFunctions;
+use pool::{Pool, PoolPermit};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use snapshot::Snapshot;
 use std::{
+    borrow::Cow,
     fmt::Debug,
     path::PathBuf,
-    sync::{Arc, Mutex},
-    thread::{self},
-    time::Duration,
+    slice,
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use wasi_common::WasiCtx;
+use ticker::EpochTicker;
+use wasi_common::{pipe::WritePipe, WasiCtx};
 use wasmtime::*;
 use wasmtime_wasi::sync::WasiCtxBuilder;
 
+pub use builder::{ModuleSource, QuickJSBuilder, Stdio};
+pub use clock::TimerMode;
+pub use compiled::CompiledScript;
+pub use error::ExecutionError;
+pub use format::WireFormat;
+pub use handle::ExecutionHandle;
+pub use inputs::Inputs;
+pub use lazy::{DataProvider, LazyValue, PathSegment};
+pub use modules::ModuleResolver;
+pub use options::{ExecutionOptions, ExecutionOutput, Executor};
+pub use session::{Session, SessionExecutor};
+pub use wasmtime::OptLevel;
+
 static PAGE_SIZE: u32 = 65536;
+static SCRIPT_NAME: &str = "script.js";
 static EPOCH_INTERVAL: u64 = 100;
+static YIELD_INTERVAL: u64 = 1000;
+/// the wire format value of raw bytes passed to the guest, see [`QuickJS::try_execute_binary`]
+static BINARY_FORMAT: i32 = 3;
 
 pub struct QuickJS {
     engine: Engine,
-    module: Module,
-    inherit_stdout: bool,
-    inherit_stderr: bool,
+    instance_pre: InstancePre<State>,
+    module_hash: [u8; 32],
+    stdout: Stdio,
+    stderr: Stdio,
     memory_limit: Option<u32>,
     time_limit: Option<TimeLimit>,
+    fuel_limit: Option<FuelLimit>,
+    cancellable: bool,
+    async_support: bool,
+    timer_mode: TimerMode,
+    wire_format: WireFormat,
+    ticker: Option<EpochTicker>,
+    pool: Option<Pool>,
+    host_functions: Arc<HostFunctions>,
 }
 
 /// A time limit to prevent long executions.
@@ -49,9 +105,32 @@ impl TimeLimit {
     }
 }
 
+/// A deterministic budget of wasm instructions to prevent long executions.
+///
+/// Unlike [`TimeLimit`] the outcome does not depend on the load of the machine. Every wasm
+/// instruction executed by the guest (including the quickjs interpreter itself) consumes fuel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FuelLimit {
+    /// the fuel available to each execution
+    pub fuel: u64,
+}
+
+impl FuelLimit {
+    pub fn new(fuel: u64) -> Self {
+        Self { fuel }
+    }
+}
+
 impl QuickJS {
+    /// configure a new QuickJS engine
+    pub fn builder() -> QuickJSBuilder {
+        QuickJSBuilder::new()
+    }
+
     /// try to instantiate a new QuickJS engine
     ///
+    /// prefer [`QuickJS::builder`] which exposes every option.
+    ///
     /// parameters:
     /// - `path`: optional override for the quickjs.wasm instance
     /// - `inherit_stdout`: route `console.log` calls to stdout
@@ -65,29 +144,63 @@ impl QuickJS {
         memory_limit: Option<u32>,
         time_limit: Option<TimeLimit>,
     ) -> Result<Self> {
-        let engine = Engine::new(Config::default().epoch_interruption(time_limit.is_some()))?;
-
-        // engine global level interrupt
-        if let Some(time_limit) = &time_limit {
-            let evaluation_interval = time_limit.evaluation_interval;
-            let engine_clone = engine.clone();
-            thread::spawn(move || loop {
-                thread::sleep(evaluation_interval);
-                engine_clone.increment_epoch();
-            });
+        let stdio = |inherit| if inherit { Stdio::Inherit } else { Stdio::Null };
+
+        let mut builder = Self::builder()
+            .stdout(stdio(inherit_stdout))
+            .stderr(stdio(inherit_stderr));
+        if let Some(path) = path {
+            builder = builder.module_path(path);
+        }
+        if let Some(memory_limit) = memory_limit {
+            builder = builder.memory_limit(memory_limit);
         }
+        if let Some(time_limit) = time_limit {
+            builder = builder.time_limit(time_limit);
+        }
+        builder.build()
+    }
 
-        let module = match path {
-            Some(path) => Module::from_file(&engine, path)?,
-            None => Module::from_binary(&engine, include_bytes!("../../../quickjs.wasm"))?,
+    fn from_parts(
+        engine: Engine,
+        module: Module,
+        module_hash: [u8; 32],
+        pool: Option<Pool>,
+        builder: QuickJSBuilder,
+    ) -> Result<Self> {
+        // engine global level interrupt, only ticking while an execution is in flight. async
+        // executions also need it to yield periodically.
+        let interval = match (&builder.time_limit, builder.async_support) {
+            (Some(time_limit), _) => Some(time_limit.evaluation_interval),
+            (None, true) => Some(Duration::from_micros(YIELD_INTERVAL)),
+            (None, false) => None,
         };
+        let ticker = interval
+            .map(|interval| EpochTicker::spawn(engine.clone(), interval))
+            .transpose()?;
+
+        // link the host imports once so each execution only has to instantiate
+        let mut linker = Linker::new(&engine);
+        wasmtime_wasi::add_to_linker(&mut linker, |state: &mut State| &mut state.wasi)?;
+        State::add_to_linker(&mut linker, builder.async_support)?;
+        let instance_pre = linker.instantiate_pre(&module)?;
+
         Ok(Self {
             engine,
-            module,
-            inherit_stdout,
-            inherit_stderr,
-            memory_limit,
-            time_limit,
+            instance_pre,
+            module_hash,
+            stdout: builder.stdout,
+            stderr: builder.stderr,
+            memory_limit: builder.memory_limit,
+            time_limit: builder.time_limit,
+            fuel_limit: builder.fuel_limit,
+            cancellable: builder.cancellable,
+            async_support: builder.async_support,
+            timer_mode: builder.timer_mode,
+            wire_format: builder.wire_format,
+            ticker,
+            pool,
+            host_functions: Arc::new(builder.host_functions),
         })
     }
 }
@@ -95,261 +208,2116 @@ impl QuickJS {
 impl Debug for QuickJS {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("QuickJS")
-            .field("inherit_stdout", &self.inherit_stdout)
-            .field("inherit_stderr", &self.inherit_stderr)
+            .field("stdout", &self.stdout)
+            .field("stderr", &self.stderr)
             .field("memory_limit", &self.memory_limit)
             .field("time_limit", &self.time_limit)
+            .field("fuel_limit", &self.fuel_limit)
+            .field("cancellable", &self.cancellable)
+            .field("async_support", &self.async_support)
+            .field("timer_mode", &self.timer_mode)
+            .field("wire_format", &self.wire_format)
+            .field("ticker", &self.ticker)
+            .field("pool", &self.pool)
+            .field("host_functions", &self.host_functions)
             .finish()
     }
 }
+/// Per execution state of a store.
 struct State {
     pub wasi: WasiCtx,
     pub limits: StoreLimits,
+    pub script: Vec<u8>,
+    pub script_kind: ScriptKind,
+    pub script_name: Vec<u8>,
+    pub data: Data,
+    pub wire_format: WireFormat,
+    pub binary: bool,
+    pub function: Vec<u8>,
+    pub modules: Vec<u8>,
+    pub inputs: Vec<u8>,
+    pub lazy_data: Option<Arc<dyn DataProvider>>,
+    pub resolver: Option<Arc<dyn ModuleResolver>>,
+    pub output: Option<Result<Vec<u8>, ExecutionError>>,
+    pub host_functions: Arc<HostFunctions>,
+    pub host_result: Vec<u8>,
+    pub clock: Clock,
 }
 
-impl QuickJS {
-    pub fn try_execute(&self, script: &str, data: Option<&str>) -> Result<Option<String>> {
-        let script = script.as_bytes().to_vec();
-        let script_size = script.len() as i32;
-        let data = data
-            .map(|data| data.as_bytes().to_vec())
-            .unwrap_or_default();
-        let data_size = data.len() as i32;
-        let output = Arc::new(Mutex::new(None));
+/// The data of an invocation in [`State`], borrowed from the caller when possible so it is only
+/// copied into guest memory.
+#[derive(Default)]
+enum Data {
+    #[default]
+    Empty,
+    Owned(Vec<u8>),
+    /// a slice borrowed by the [`Invocation`] it was passed with
+    Borrowed(*const u8, usize),
+}
 
-        let mut linker = Linker::new(&self.engine);
-        wasmtime_wasi::add_to_linker(&mut linker, |state: &mut State| &mut state.wasi)?;
+// SAFETY: a borrowed slice is only read from the store, which is either dropped before the call
+// the invocation was passed to returns or resets its data, see `Session::invoke`
+unsafe impl Send for Data {}
 
-        let mut wasi_ctx_builder = WasiCtxBuilder::new();
-        if self.inherit_stdout {
-            wasi_ctx_builder.inherit_stdout();
-        };
-        if self.inherit_stderr {
-            wasi_ctx_builder.inherit_stderr();
-        };
+impl Data {
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            Data::Empty => &[],
+            Data::Owned(data) => data,
+            // SAFETY: see `impl Send for Data`
+            Data::Borrowed(ptr, len) => unsafe { slice::from_raw_parts(*ptr, *len) },
+        }
+    }
+}
 
-        let wasi = wasi_ctx_builder.build();
+impl From<Cow<'_, [u8]>> for Data {
+    fn from(data: Cow<'_, [u8]>) -> Self {
+        match data {
+            Cow::Owned(data) => Data::Owned(data),
+            Cow::Borrowed(data) => Data::Borrowed(data.as_ptr(), data.len()),
+        }
+    }
+}
 
-        // setup memory limits and
-        let (memory_type, limits) = match self.memory_limit {
-            Some(memory_limit) => (
-                MemoryType::new(memory_limit / PAGE_SIZE, Some(memory_limit / PAGE_SIZE)),
-                StoreLimitsBuilder::new()
-                    .instances(1)
-                    .memory_size(memory_limit as usize)
-                    .build(),
-            ),
-            None => (
-                MemoryType::new(1, None),
-                StoreLimitsBuilder::new().instances(1).build(),
-            ),
-        };
+/// The result of running the guest once.
+struct Execution {
+    output: Option<Result<Vec<u8>, ExecutionError>>,
+}
 
-        let mut store = Store::new(&self.engine, State { wasi, limits });
-        store.limiter(move |state| &mut state.limits);
+impl Execution {
+    /// the raw output or the exception raised by the script
+    fn output(self) -> Result<Option<Vec<u8>>, ExecutionError> {
+        self.output.transpose()
+    }
+}
 
-        if let Some(time_limit) = &self.time_limit {
-            // calculate number of epochs to meet timeout for this execution
-            let mut epoch_limit = u32::try_from(
-                time_limit.limit.as_micros() / time_limit.evaluation_interval.as_micros(),
-            )?;
-            store.epoch_deadline_callback(move |_| {
-                epoch_limit -= 1;
-                if epoch_limit == 0 {
-                    bail!("exceeds time limit");
-                }
-                Ok(UpdateDeadline::Continue(1))
-            });
-            store.set_epoch_deadline(1);
-        }
+/// A store ready to run the guest once and the resources held until it finishes.
+struct Run<'a> {
+    store: Store<State>,
+    stdout: Option<CaptureBuffer>,
+    stderr: Option<CaptureBuffer>,
+    _permit: Option<PoolPermit<'a>>,
+}
+
+/// How the guest treats the script of an execution.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(i32)]
+enum ScriptKind {
+    /// evaluate the source
+    #[default]
+    Source = 0,
+    /// evaluate bytecode produced by [`ScriptKind::Compile`]
+    Bytecode = 1,
+    /// compile the source and output its bytecode without evaluating it
+    Compile = 2,
+}
+
+/// The inputs of a single execution passed to the guest through [`State`].
+#[derive(Default)]
+struct Invocation<'a> {
+    script: &'a str,
+    /// replaces `script` with bytecode
+    bytecode: Option<&'a [u8]>,
+    /// compile `script` with this name instead of evaluating it
+    compile: Option<&'a str>,
+    data: Cow<'a, [u8]>,
+    /// encoding of `data` and the output
+    format: WireFormat,
+    /// pass `data` and the output as raw bytes instead of `format`
+    binary: bool,
+    function: Option<&'a str>,
+    modules: Vec<u8>,
+    /// loads the modules imported by the guest
+    resolver: Option<Arc<dyn ModuleResolver>>,
+    /// the names `data` is bound to, see [`Inputs`]
+    inputs: Vec<u8>,
+    /// resolves `data` lazily instead of passing it
+    lazy_data: Option<Arc<dyn DataProvider>>,
+}
 
-        Memory::new(&mut store, memory_type)?;
+impl State {
+    /// pass the inputs of the next call into the guest
+    fn invoke(&mut self, invocation: Invocation<'_>) {
+        self.script_kind = match (invocation.bytecode, invocation.compile) {
+            (Some(_), _) => ScriptKind::Bytecode,
+            (None, Some(_)) => ScriptKind::Compile,
+            (None, None) => ScriptKind::Source,
+        };
+        self.script = invocation
+            .bytecode
+            .unwrap_or(invocation.script.as_bytes())
+            .to_vec();
+        self.script_name = invocation
+            .compile
+            .map(|name| name.as_bytes().to_vec())
+            .unwrap_or_default();
+        self.data = invocation.data.into();
+        self.wire_format = invocation.format;
+        self.binary = invocation.binary;
+        self.function = invocation
+            .function
+            .map(|function| function.as_bytes().to_vec())
+            .unwrap_or_default();
+        self.modules = invocation.modules;
+        self.inputs = invocation.inputs;
+        self.lazy_data = invocation.lazy_data;
+        self.resolver = invocation.resolver;
+        self.output = None;
+    }
 
+    /// add the `host` imports used by quickjs-wasm to read its input and write its output
+    fn add_to_linker(linker: &mut Linker<State>, async_support: bool) -> Result<()> {
         linker.func_wrap(
             "host",
             "get_script_size",
-            move |_: Caller<'_, State>| -> Result<i32> { Ok(script_size) },
+            |caller: Caller<'_, State>| -> Result<i32> { Ok(caller.data().script.len() as i32) },
         )?;
 
         linker.func_wrap(
             "host",
             "get_script",
-            move |mut caller: Caller<'_, State>, ptr: i32| -> Result<()> {
-                let memory = match caller.get_export("memory") {
-                    Some(Extern::Memory(memory)) => memory,
-                    _ => return Err(anyhow!("failed to find host memory")),
-                };
-                let offset = ptr as u32 as usize;
-                Ok(memory.write(&mut caller, offset, &script)?)
+            |mut caller: Caller<'_, State>, ptr: i32| -> Result<()> {
+                write_to_guest(&mut caller, ptr, |state| state.script.as_slice())
+            },
+        )?;
+
+        linker.func_wrap(
+            "host",
+            "get_script_kind",
+            |caller: Caller<'_, State>| -> i32 { caller.data().script_kind as i32 },
+        )?;
+
+        linker.func_wrap(
+            "host",
+            "get_script_name_size",
+            |caller: Caller<'_, State>| -> Result<i32> {
+                Ok(caller.data().script_name.len() as i32)
+            },
+        )?;
+
+        linker.func_wrap(
+            "host",
+            "get_script_name",
+            |mut caller: Caller<'_, State>, ptr: i32| -> Result<()> {
+                write_to_guest(&mut caller, ptr, |state| state.script_name.as_slice())
             },
         )?;
 
         linker.func_wrap(
             "host",
             "get_data_size",
-            move |_: Caller<'_, State>| -> Result<i32> { Ok(data_size) },
+            |caller: Caller<'_, State>| -> Result<i32> {
+                Ok(caller.data().data.as_slice().len() as i32)
+            },
         )?;
 
         linker.func_wrap(
             "host",
             "get_data",
-            move |mut caller: Caller<'_, State>, ptr: i32| -> Result<()> {
-                let memory = match caller.get_export("memory") {
-                    Some(Extern::Memory(memory)) => memory,
-                    _ => return Err(anyhow!("failed to find host memory")),
-                };
-                let offset = ptr as u32 as usize;
-                Ok(memory.write(&mut caller, offset, &data)?)
+            |mut caller: Caller<'_, State>, ptr: i32| -> Result<()> {
+                write_to_guest(&mut caller, ptr, |state| state.data.as_slice())
+            },
+        )?;
+
+        linker.func_wrap(
+            "host",
+            "get_wire_format",
+            |caller: Caller<'_, State>| -> i32 {
+                match caller.data().binary {
+                    true => BINARY_FORMAT,
+                    false => caller.data().wire_format as i32,
+                }
+            },
+        )?;
+
+        linker.func_wrap(
+            "host",
+            "get_function_size",
+            |caller: Caller<'_, State>| -> Result<i32> { Ok(caller.data().function.len() as i32) },
+        )?;
+
+        linker.func_wrap(
+            "host",
+            "get_function",
+            |mut caller: Caller<'_, State>, ptr: i32| -> Result<()> {
+                write_to_guest(&mut caller, ptr, |state| state.function.as_slice())
+            },
+        )?;
+
+        linker.func_wrap(
+            "host",
+            "get_modules_size",
+            |caller: Caller<'_, State>| -> Result<i32> { Ok(caller.data().modules.len() as i32) },
+        )?;
+
+        linker.func_wrap(
+            "host",
+            "get_modules",
+            |mut caller: Caller<'_, State>, ptr: i32| -> Result<()> {
+                write_to_guest(&mut caller, ptr, |state| state.modules.as_slice())
+            },
+        )?;
+
+        linker.func_wrap(
+            "host",
+            "get_inputs_size",
+            |caller: Caller<'_, State>| -> Result<i32> { Ok(caller.data().inputs.len() as i32) },
+        )?;
+
+        linker.func_wrap(
+            "host",
+            "get_inputs",
+            |mut caller: Caller<'_, State>, ptr: i32| -> Result<()> {
+                write_to_guest(&mut caller, ptr, |state| state.inputs.as_slice())
             },
         )?;
 
-        let output_clone = output.clone();
         linker.func_wrap(
             "host",
             "set_output",
-            move |mut caller: Caller<'_, State>,
-                  ptr: i32,
-                  capacity: i32,
-                  error: i32|
-                  -> Result<()> {
-                let mut output = output_clone.lock().unwrap();
-
-                *output = if capacity == 0 {
+            |mut caller: Caller<'_, State>, ptr: i32, capacity: i32, error: i32| -> Result<()> {
+                let output = if capacity == 0 {
                     None
                 } else {
-                    let memory = match caller.get_export("memory") {
-                        Some(Extern::Memory(memory)) => memory,
-                        _ => return Err(anyhow!("failed to find host memory")),
-                    };
-                    let offset = ptr as u32 as usize;
-                    let mut buffer: Vec<u8> = vec![0; capacity as usize];
-                    memory.read(&caller, offset, &mut buffer)?;
-
-                    let result = String::from_utf8(buffer)?;
-                    Some(if error == 1 {
-                        Err(anyhow!(result))
-                    } else {
-                        Ok(result)
+                    let buffer = read_from_guest(&mut caller, ptr, capacity)?;
+
+                    Some(match error {
+                        0 => Ok(buffer),
+                        2 => Err(ExecutionError::FunctionNotFound(
+                            String::from_utf8_lossy(&buffer).into_owned(),
+                        )),
+                        3 => Err(ExecutionError::NotCallable(
+                            String::from_utf8_lossy(&buffer).into_owned(),
+                        )),
+                        _ => Err(ExecutionError::from_exception(&buffer)),
                     })
                 };
+                caller.data_mut().output = output;
 
                 Ok(())
             },
         )?;
 
-        linker.module(&mut store, "", &self.module)?;
+        linker.func_wrap(
+            "host",
+            "get_host_functions_size",
+            |caller: Caller<'_, State>| -> Result<i32> {
+                Ok(caller.data().host_functions.names().len() as i32)
+            },
+        )?;
+
+        linker.func_wrap(
+            "host",
+            "get_host_functions",
+            |mut caller: Caller<'_, State>, ptr: i32| -> Result<()> {
+                write_to_guest(&mut caller, ptr, |state| state.host_functions.names())
+            },
+        )?;
+
+        // returns 1 if the host function failed in which case the result is the error message
+        linker.func_wrap(
+            "host",
+            "call_host_function",
+            |mut caller: Caller<'_, State>,
+             name_ptr: i32,
+             name_size: i32,
+             args_ptr: i32,
+             args_size: i32|
+             -> Result<i32> {
+                let name = read_from_guest(&mut caller, name_ptr, name_size)?;
+                let args = read_from_guest(&mut caller, args_ptr, args_size)?;
+
+                let state = caller.data_mut();
+                let (status, result) = match state
+                    .host_functions
+                    .call(&String::from_utf8_lossy(&name), &args)
+                {
+                    Ok(result) => (0, result),
+                    Err(err) => (1, err.to_string().into_bytes()),
+                };
+                state.host_result = result;
+
+                Ok(status)
+            },
+        )?;
+
+        linker.func_wrap(
+            "host",
+            "has_lazy_data",
+            |caller: Caller<'_, State>| -> i32 { caller.data().lazy_data.is_some() as i32 },
+        )?;
+
+        linker.func_wrap(
+            "host",
+            "get_lazy_data",
+            |mut caller: Caller<'_, State>, path_ptr: i32, path_size: i32| -> Result<i32> {
+                let path = read_from_guest(&mut caller, path_ptr, path_size)?;
+
+                let state = caller.data_mut();
+                let (status, result) = match serde_json::from_slice::<Vec<PathSegment>>(&path) {
+                    Ok(path) => {
+                        let value = state
+                            .lazy_data
+                            .as_ref()
+                            .and_then(|provider| provider.get(&path));
+                        (0, serde_json::to_vec(&value)?)
+                    }
+                    Err(err) => (1, err.to_string().into_bytes()),
+                };
+                state.host_result = result;
+
+                Ok(status)
+            },
+        )?;
+
+        // loads a module imported by the guest into the host result. returns 1 if it does not
+        // exist in which case the guest throws at the import
+        linker.func_wrap(
+            "host",
+            "resolve_module",
+            |mut caller: Caller<'_, State>, name_ptr: i32, name_size: i32| -> Result<i32> {
+                let name = String::from_utf8(read_from_guest(&mut caller, name_ptr, name_size)?)?;
+
+                let state = caller.data_mut();
+                let source = state
+                    .resolver
+                    .as_ref()
+                    .and_then(|resolver| resolver.resolve(&name));
+                let (status, result) = match source {
+                    Some(source) => (0, source.into_bytes()),
+                    None => (1, Vec::new()),
+                };
+                state.host_result = result;
+
+                Ok(status)
+            },
+        )?;
+
+        linker.func_wrap(
+            "host",
+            "get_host_result_size",
+            |caller: Caller<'_, State>| -> Result<i32> {
+                Ok(caller.data().host_result.len() as i32)
+            },
+        )?;
+
+        linker.func_wrap(
+            "host",
+            "get_host_result",
+            |mut caller: Caller<'_, State>, ptr: i32| -> Result<()> {
+                write_to_guest(&mut caller, ptr, |state| state.host_result.as_slice())
+            },
+        )?;
+
+        linker.func_wrap("host", "clock_now", |caller: Caller<'_, State>| -> f64 {
+            caller.data().clock.now()
+        })?;
 
-        // call the default function i.e. main()
-        linker
-            .get_default(&mut store, "")?
-            .typed::<(), ()>(&store)?
-            .call(&mut store, ())?;
+        // waits for the next timer of the guest event loop, until it is due or the execution is
+        // cancelled. an async execution yields to the executor instead of blocking its thread.
+        if async_support {
+            linker.func_wrap1_async(
+                "host",
+                "clock_wait_until",
+                |mut caller: Caller<'_, State>, due: f64| {
+                    Box::new(async move {
+                        let wake = caller.data_mut().clock.wait_until(due)?;
+                        while let Some(duration) = caller.data().clock.next_sleep(wake)? {
+                            Delay::new(duration).await;
+                        }
+                        Ok(())
+                    })
+                },
+            )?;
+        } else {
+            linker.func_wrap(
+                "host",
+                "clock_wait_until",
+                |mut caller: Caller<'_, State>, due: f64| -> Result<()> {
+                    let wake = caller.data_mut().clock.wait_until(due)?;
+                    while let Some(duration) = caller.data().clock.next_sleep(wake)? {
+                        std::thread::sleep(duration);
+                    }
+                    Ok(())
+                },
+            )?;
+        }
 
-        let mut output = output.lock().unwrap();
-        output.take().transpose()
+        Ok(())
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// find the exported linear memory of the guest
+fn guest_memory(caller: &mut Caller<'_, State>) -> Result<Memory> {
+    match caller.get_export("memory") {
+        Some(Extern::Memory(memory)) => Ok(memory),
+        _ => Err(anyhow!("failed to find host memory")),
+    }
+}
 
-    #[test]
-    fn try_execute() {
-        let quickjs = QuickJS::try_new(None, false, false, None, None).unwrap();
+/// copy `size` bytes out of guest memory at `ptr`
+fn read_from_guest(caller: &mut Caller<'_, State>, ptr: i32, size: i32) -> Result<Vec<u8>> {
+    let memory = guest_memory(caller)?;
+    let offset = ptr as u32 as usize;
+    let mut buffer: Vec<u8> = vec![0; size as u32 as usize];
+    memory.read(&*caller, offset, &mut buffer)?;
+    Ok(buffer)
+}
 
-        let script = r#"
-            'quickjs' + 'wasm'
-        "#;
+/// copy bytes held by the store state into guest memory at `ptr`
+fn write_to_guest(
+    caller: &mut Caller<'_, State>,
+    ptr: i32,
+    bytes: impl Fn(&State) -> &[u8],
+) -> Result<()> {
+    let memory = guest_memory(caller)?;
+    let (memory, state) = memory.data_and_store_mut(caller);
+    let bytes = bytes(state);
+    let offset = ptr as u32 as usize;
+    memory
+        .get_mut(offset..offset + bytes.len())
+        .ok_or_else(|| anyhow!("out of bounds memory access"))?
+        .copy_from_slice(bytes);
+    Ok(())
+}
 
-        let result = quickjs.try_execute(script, None).unwrap();
+/// the number of epochs ticked every `interval` until `remaining` has elapsed
+fn epochs_within(remaining: Duration, interval: Duration) -> u64 {
+    u64::try_from(remaining.as_nanos().div_ceil(interval.as_nanos()))
+        .unwrap_or(u64::MAX / 2)
+        .max(1)
+}
 
-        assert_eq!(result, Some("\"quickjswasm\"".to_string()));
+/// decode a JSON encoded output as a string
+fn decode_output(output: Option<Vec<u8>>) -> Result<Option<String>, ExecutionError> {
+    output
+        .map(|output| {
+            String::from_utf8(output).map_err(|err| ExecutionError::InvalidOutput(Box::new(err)))
+        })
+        .transpose()
+}
+
+impl QuickJS {
+    /// run the entry points with per execution `options`, i.e. an [`ExecutionHandle`] to cancel
+    /// the execution or an [`ExecutionOutput`] to capture its console output and fuel consumed
+    pub fn with_options<'a>(&'a self, options: ExecutionOptions<'a>) -> Executor<'a> {
+        Executor::new(self, options)
     }
 
-    #[test]
-    fn try_execute_data() {
-        let quickjs = QuickJS::try_new(None, false, false, None, None).unwrap();
+    /// execute `script` with `data` bound to the global `data` and return the JSON encoded
+    /// result of the last expression
+    ///
+    /// a script using top-level `await` is evaluated as an ES module instead: it runs in strict
+    /// mode, its declarations are scoped to the module instead of defining globals and its result
+    /// is its default export (`undefined` without one) once its `await`s settled.
+    pub fn try_execute(
+        &self,
+        script: &str,
+        data: Option<&str>,
+    ) -> Result<Option<String>, ExecutionError> {
+        self.with_options(ExecutionOptions::default())
+            .try_execute(script, data)
+    }
 
-        let script = r#"
-            'quickjs' + data.input
-        "#;
+    /// a handle to abort an execution from another thread, passed with
+    /// [`ExecutionOptions::handle`]
+    pub fn execution_handle(&self) -> ExecutionHandle {
+        ExecutionHandle::new(self.engine.clone())
+    }
 
-        let data = r#"{"input": "wasm"}"#;
+    /// like [`QuickJS::try_execute`] but runs on the async executor, yielding to it periodically
+    /// instead of blocking the thread. requires [`QuickJSBuilder::async_support`].
+    pub async fn try_execute_async(
+        &self,
+        script: &str,
+        data: Option<&str>,
+    ) -> Result<Option<String>, ExecutionError> {
+        self.with_options(ExecutionOptions::default())
+            .try_execute_async(script, data)
+            .await
+    }
 
-        let result = quickjs.try_execute(script, Some(data)).unwrap();
+    /// compile `script` to bytecode once so executions with [`QuickJS::try_execute_compiled`] skip
+    /// parsing it. a syntax error is returned as [`ExecutionError::JsException`].
+    pub fn compile(&self, script: &str) -> Result<CompiledScript, ExecutionError> {
+        self.with_options(ExecutionOptions::default())
+            .compile(script)
+    }
 
-        assert_eq!(result, Some("\"quickjswasm\"".to_string()));
+    /// like [`QuickJS::compile`] with the `name` the script is reported as in exception stacks
+    pub fn compile_named(
+        &self,
+        name: &str,
+        script: &str,
+    ) -> Result<CompiledScript, ExecutionError> {
+        self.with_options(ExecutionOptions::default())
+            .compile_named(name, script)
     }
 
-    #[test]
-    fn try_throw_error() {
-        let quickjs = QuickJS::try_new(None, false, false, None, None).unwrap();
+    /// like [`QuickJS::try_execute`] for a script compiled with [`QuickJS::compile`]
+    pub fn try_execute_compiled(
+        &self,
+        script: &CompiledScript,
+        data: Option<&str>,
+    ) -> Result<Option<String>, ExecutionError> {
+        self.with_options(ExecutionOptions::default())
+            .try_execute_compiled(script, data)
+    }
 
-        let script = r#"
-            throw new Error('myerror');
-        "#;
+    /// execute `script` with `input` serialized to the global `data` and deserialize the result
+    /// of the last expression
+    ///
+    /// a script without a result (i.e. `undefined`) deserializes from `null`.
+    pub fn try_execute_typed<I, O>(&self, script: &str, input: &I) -> Result<O, ExecutionError>
+    where
+        I: Serialize + ?Sized,
+        O: DeserializeOwned,
+    {
+        self.with_options(ExecutionOptions::default())
+            .try_execute_typed(script, input)
+    }
 
-        match quickjs.try_execute(script, None) {
-            Err(err) if err.to_string().contains("Uncaught Error: myerror") => {}
-            other => panic!("{:?}", other),
-        }
+    /// execute `script` with an optional `data` value and return the result of the last
+    /// expression as a [`Value`]
+    pub fn try_execute_value(
+        &self,
+        script: &str,
+        data: Option<&Value>,
+    ) -> Result<Value, ExecutionError> {
+        self.with_options(ExecutionOptions::default())
+            .try_execute_value(script, data)
     }
 
-    #[test]
-    fn try_execute_memory_limit_normal() {
-        let quickjs = QuickJS::try_new(None, false, false, Some(4194304), None).unwrap();
+    /// execute `script` with `data` encoded in the [`WireFormat`] of the builder and return the
+    /// result of the last expression in the same format, i.e. MessagePack bytes from another
+    /// service without decoding them on the host
+    pub fn try_execute_bytes(
+        &self,
+        script: &str,
+        data: Option<&[u8]>,
+    ) -> Result<Option<Vec<u8>>, ExecutionError> {
+        self.with_options(ExecutionOptions::default())
+            .try_execute_bytes(script, data)
+    }
 
-        let script = r#"
-            'quickjs' + 'wasm'
-        "#;
+    /// execute `script` with `data` bound to the global `data` as a `Uint8Array` and return the
+    /// bytes of the `ArrayBuffer` or typed array (i.e. `Uint8Array` or `DataView`) it evaluates
+    /// to, for images, protobufs or compressed blobs
+    ///
+    /// a script without a result returns no bytes and any other result fails with
+    /// [`ExecutionError::JsException`].
+    pub fn try_execute_binary(&self, script: &str, data: &[u8]) -> Result<Vec<u8>, ExecutionError> {
+        self.with_options(ExecutionOptions::default())
+            .try_execute_binary(script, data)
+    }
 
-        let result = quickjs.try_execute(script, None).unwrap();
+    /// evaluate `script` then call the function `function` with `args` and return its result
+    ///
+    /// the function is looked up as a global or as a property of the value `script` evaluates to
+    /// i.e. `({ transform })`. a script using top-level `await` is evaluated as an ES module, see
+    /// [`QuickJS::try_execute`], and the function is looked up among its exports once its `await`s
+    /// settled.
+    pub fn call(
+        &self,
+        script: &str,
+        function: &str,
+        args: &[Value],
+    ) -> Result<Value, ExecutionError> {
+        self.with_options(ExecutionOptions::default())
+            .call(script, function, args)
+    }
 
-        assert_eq!(result, Some("\"quickjswasm\"".to_string()));
+    /// execute `script` with each of `inputs` bound to its own global instead of `data` and
+    /// return the JSON encoded result of the last expression
+    pub fn try_execute_inputs(
+        &self,
+        script: &str,
+        inputs: &Inputs,
+    ) -> Result<Option<String>, ExecutionError> {
+        self.with_options(ExecutionOptions::default())
+            .try_execute_inputs(script, inputs)
     }
 
-    #[test]
-    fn try_execute_memory_limit_exceed() {
-        let quickjs = QuickJS::try_new(None, false, false, Some(4194304), None).unwrap();
+    /// like [`QuickJS::call`] with the values of `inputs` as the arguments in insertion order,
+    /// frozen ones are frozen before the call
+    pub fn call_inputs(
+        &self,
+        script: &str,
+        function: &str,
+        inputs: &Inputs,
+    ) -> Result<Value, ExecutionError> {
+        self.with_options(ExecutionOptions::default())
+            .call_inputs(script, function, inputs)
+    }
 
-        let script = r#"
-            let memory = [];
-            while (true) {
-                memory.push("allocate");
-            }
-        "#;
+    /// execute `script` with `data` bound to a read-only proxy resolving each value the script
+    /// reads from `provider`, so only the values actually touched are copied into the guest
+    ///
+    /// useful for large datasets of which a script reads a few fields or that would exceed the
+    /// memory limit. `Array.isArray`, iteration and `JSON.stringify` work on the proxy.
+    pub fn try_execute_lazy(
+        &self,
+        script: &str,
+        provider: Arc<dyn DataProvider>,
+    ) -> Result<Option<String>, ExecutionError> {
+        self.with_options(ExecutionOptions::default())
+            .try_execute_lazy(script, provider)
+    }
 
-        match quickjs.try_execute(script, None) {
-            Err(err) if err.to_string().contains("out of memory") => {}
-            other => panic!("{:?}", other),
-        }
+    /// execute the ES module `entry` with `data` bound to the global `data` and return the JSON
+    /// encoded `export` of it, i.e. `"default"`
+    ///
+    /// the entry and every module imported statically or with `import()` are loaded from
+    /// `resolver` when the guest first imports them. a missing module throws a
+    /// `ModuleNotFoundError` at the import, which fails the execution with
+    /// [`ExecutionError::ModuleNotFound`] unless the script catches it.
+    pub fn try_execute_module(
+        &self,
+        entry: &str,
+        export: &str,
+        resolver: Arc<dyn ModuleResolver>,
+        data: Option<&str>,
+    ) -> Result<Option<String>, ExecutionError> {
+        self.with_options(ExecutionOptions::default())
+            .try_execute_module(entry, export, resolver, data)
     }
 
-    #[test]
-    fn try_execute_time_limit() {
-        let quickjs = QuickJS::try_new(
-            None,
-            false,
-            false,
-            None,
-            Some(TimeLimit::new(Duration::from_secs(2))),
-        )
-        .unwrap();
+    /// start a [`Session`] that keeps its globals across calls. the memory limit and pool slot
+    /// are held until it is dropped.
+    pub fn session(&self) -> Result<Session<'_>, ExecutionError> {
+        Ok(Session::new(self, None)?)
+    }
 
-        let script = r#"
-            function sleep(milliseconds) {
-                const date = Date.now();
-                let currentDate = null;
-                do {
-                    currentDate = Date.now();
-                } while (currentDate - date < milliseconds);
-            }
-            sleep(30000);
-        "#;
+    /// start a [`Session`] from a [`Session::snapshot`] of this or another `QuickJS` built from
+    /// the same module. a snapshot of a different module fails with
+    /// [`ExecutionError::InvalidInput`].
+    pub fn restore(&self, snapshot: &[u8]) -> Result<Session<'_>, ExecutionError> {
+        let snapshot = Snapshot::decode(snapshot)?;
+        Ok(Session::new(self, Some(&snapshot))?)
+    }
+
+    fn execute(
+        &self,
+        invocation: Invocation<'_>,
+        options: ExecutionOptions<'_>,
+    ) -> Result<Execution> {
+        if self.async_support {
+            bail!("QuickJS is built with async_support, use the async methods");
+        }
+
+        // tick the epoch while this execution is in flight
+        let _active = self.ticker.as_ref().map(EpochTicker::activate);
+        let mut run = self.prepare(invocation, options.handle)?;
+        let instance = self.instance_pre.instantiate(&mut run.store)?;
+
+        // call the command entrypoint i.e. main()
+        let result = instance
+            .get_typed_func::<(), ()>(&mut run.store, "_start")?
+            .call(&mut run.store, ());
+
+        self.finish(run, instance, result, options.output)
+    }
+
+    async fn execute_async(
+        &self,
+        invocation: Invocation<'_>,
+        options: ExecutionOptions<'_>,
+    ) -> Result<Execution> {
+        if !self.async_support {
+            bail!("async execution requires QuickJSBuilder::async_support");
+        }
+
+        let _active = self.ticker.as_ref().map(EpochTicker::activate);
+        let mut run = self.prepare(invocation, options.handle)?;
+        let instance = self.instance_pre.instantiate_async(&mut run.store).await?;
+
+        // call the command entrypoint, yielding to the executor on every epoch
+        let result = instance
+            .get_typed_func::<(), ()>(&mut run.store, "_start")?
+            .call_async(&mut run.store, ())
+            .await;
+
+        self.finish(run, instance, result, options.output)
+    }
+
+    /// create the store for a single execution
+    fn prepare(
+        &self,
+        invocation: Invocation<'_>,
+        handle: Option<&ExecutionHandle>,
+    ) -> Result<Run<'_>> {
+        // hold a pool slot until the store is dropped
+        let permit = self.pool.as_ref().map(Pool::acquire).transpose()?;
+
+        let mut wasi_ctx_builder = WasiCtxBuilder::new();
+        let stdout = match self.stdout {
+            Stdio::Null => None,
+            Stdio::Inherit => {
+                wasi_ctx_builder.inherit_stdout();
+                None
+            }
+            Stdio::Capture { limit } => {
+                let capture = CaptureBuffer::new(limit);
+                wasi_ctx_builder.stdout(Box::new(WritePipe::new(capture.clone())));
+                Some(capture)
+            }
+        };
+        let stderr = match self.stderr {
+            Stdio::Null => None,
+            Stdio::Inherit => {
+                wasi_ctx_builder.inherit_stderr();
+                None
+            }
+            Stdio::Capture { limit } => {
+                let capture = CaptureBuffer::new(limit);
+                wasi_ctx_builder.stderr(Box::new(WritePipe::new(capture.clone())));
+                Some(capture)
+            }
+        };
+
+        let wasi = wasi_ctx_builder.build();
+
+        // setup memory limits
+        let limits = match self.memory_limit {
+            Some(memory_limit) => StoreLimitsBuilder::new()
+                .instances(1)
+                .memory_size(memory_limit as usize)
+                .build(),
+            None => StoreLimitsBuilder::new().instances(1).build(),
+        };
+
+        let mut state = State {
+            wasi,
+            limits,
+            script: Vec::new(),
+            script_kind: ScriptKind::Source,
+            script_name: Vec::new(),
+            data: Data::default(),
+            wire_format: WireFormat::Json,
+            binary: false,
+            function: Vec::new(),
+            modules: Vec::new(),
+            inputs: Vec::new(),
+            lazy_data: None,
+            resolver: None,
+            output: None,
+            host_functions: self.host_functions.clone(),
+            host_result: Vec::new(),
+            clock: Clock::new(self.timer_mode, None, None),
+        };
+        state.invoke(invocation);
+
+        let mut store = Store::new(&self.engine, state);
+        store.limiter(move |state| &mut state.limits);
+
+        self.arm(&mut store, handle)?;
+
+        Ok(Run {
+            store,
+            stdout,
+            stderr,
+            _permit: permit,
+        })
+    }
+
+    /// apply the time limit, cancellation and fuel limit to the next call into the guest
+    fn arm(&self, store: &mut Store<State>, handle: Option<&ExecutionHandle>) -> Result<()> {
+        let handle = handle.cloned();
+        if handle.is_some() && !self.cancellable {
+            bail!("cancellation requires QuickJSBuilder::cancellable");
+        }
+
+        let deadline = self
+            .time_limit
+            .as_ref()
+            .map(|time_limit| Instant::now() + time_limit.limit);
+        store.data_mut().clock = Clock::new(self.timer_mode, deadline, handle.clone());
+
+        let timeout = match (&self.time_limit, &self.ticker) {
+            (Some(_), Some(ticker)) => deadline.map(|deadline| (deadline, ticker.interval())),
+            _ => None,
+        };
+        let yields = self.async_support;
+        if timeout.is_some() || handle.is_some() || yields {
+            // the deadline is set once to the epoch the time limit elapses at, unless cancellable
+            // as `cancel` only increments the epoch once and every epoch has to be checked, or
+            // async which yields to the executor on every epoch
+            let every_epoch = handle.is_some() || yields;
+            let next_deadline = move || -> Result<u64> {
+                if let Some((deadline, interval)) = timeout {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        bail!(ExecutionError::Timeout);
+                    }
+                    if !every_epoch {
+                        return Ok(epochs_within(remaining, interval));
+                    }
+                }
+                Ok(1)
+            };
+            store.set_epoch_deadline(next_deadline()?);
+
+            let handle = handle.clone();
+            store.epoch_deadline_callback(move |_| {
+                if handle.as_ref().is_some_and(ExecutionHandle::is_cancelled) {
+                    bail!(ExecutionError::Cancelled);
+                }
+                // ticks can arrive early so the deadline is extended until the limit elapsed
+                let deadline = next_deadline()?;
+                if yields {
+                    Ok(UpdateDeadline::Yield(deadline))
+                } else {
+                    Ok(UpdateDeadline::Continue(deadline))
+                }
+            });
+        } else {
+            // epoch interruption may still be enabled for cancellation or by the precompiled
+            // module, never interrupt this store
+            store.set_epoch_deadline(u64::MAX / 2);
+        }
+
+        // checked after the deadline is set so a concurrent `cancel` either is seen here or
+        // increments the epoch past the deadline
+        if handle.as_ref().is_some_and(ExecutionHandle::is_cancelled) {
+            bail!(ExecutionError::Cancelled);
+        }
+
+        if let Some(fuel_limit) = &self.fuel_limit {
+            store.set_fuel(fuel_limit.fuel)?;
+        }
+
+        Ok(())
+    }
+
+    /// a guest allocation failure aborts with a trap once memory cannot grow any further
+    fn classify(&self, store: &mut Store<State>, instance: Instance, err: Error) -> Error {
+        if let (Some(trap), Some(memory_limit)) = (err.downcast_ref::<Trap>(), self.memory_limit) {
+            let exhausted = instance
+                .get_memory(&mut *store, "memory")
+                .map(|memory| {
+                    memory.data_size(&*store) + PAGE_SIZE as usize > memory_limit as usize
+                })
+                .unwrap_or_default();
+            if exhausted && *trap == Trap::UnreachableCodeReached {
+                return ExecutionError::OutOfMemory.into();
+            }
+        }
+        err
+    }
+
+    /// collect the output of a finished execution or classify its error
+    fn finish(
+        &self,
+        mut run: Run<'_>,
+        instance: Instance,
+        result: Result<()>,
+        output: Option<&mut ExecutionOutput>,
+    ) -> Result<Execution> {
+        if let Err(err) = result {
+            return Err(self.classify(&mut run.store, instance, err));
+        }
+
+        self.report(&run, output)?;
+
+        Ok(Execution {
+            output: run.store.into_data().output,
+        })
+    }
+
+    /// fill `output` with what the last call into the guest of `run` reported
+    fn report(&self, run: &Run<'_>, output: Option<&mut ExecutionOutput>) -> Result<()> {
+        if let Some(output) = output {
+            let take = |capture: &Option<CaptureBuffer>| {
+                let bytes = capture
+                    .as_ref()
+                    .map(CaptureBuffer::take)
+                    .unwrap_or_default();
+                String::from_utf8_lossy(&bytes).into_owned()
+            };
+            output.stdout = take(&run.stdout);
+            output.stderr = take(&run.stderr);
+            output.fuel_consumed = match &self.fuel_limit {
+                Some(fuel_limit) => Some(fuel_limit.fuel - run.store.get_fuel()?),
+                None => None,
+            };
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn try_execute() {
+        let quickjs = QuickJS::try_new(None, false, false, None, None).unwrap();
+
+        let script = r#"
+            'quickjs' + 'wasm'
+        "#;
+
+        let result = quickjs.try_execute(script, None).unwrap();
+
+        assert_eq!(result, Some("\"quickjswasm\"".to_string()));
+    }
+
+    #[test]
+    fn try_execute_data() {
+        let quickjs = QuickJS::try_new(None, false, false, None, None).unwrap();
+
+        let script = r#"
+            'quickjs' + data.input
+        "#;
+
+        let data = r#"{"input": "wasm"}"#;
+
+        let result = quickjs.try_execute(script, Some(data)).unwrap();
+
+        assert_eq!(result, Some("\"quickjswasm\"".to_string()));
+    }
+
+    #[test]
+    fn try_throw_error() {
+        let quickjs = QuickJS::try_new(None, false, false, None, None).unwrap();
+
+        let script = r#"
+            throw new Error('myerror');
+        "#;
+
+        match quickjs.try_execute(script, None) {
+            Err(err) if err.to_string().contains("Uncaught Error: myerror") => {}
+            other => panic!("{:?}", other),
+        }
+        match quickjs.try_execute(script, None) {
+            Err(ExecutionError::JsException {
+                name,
+                message,
+                stack,
+            }) => {
+                assert_eq!(name, "Error");
+                assert_eq!(message, "myerror");
+                assert!(stack.unwrap().contains("script.js"));
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn try_execute_memory_limit_normal() {
+        let quickjs = QuickJS::try_new(None, false, false, Some(4194304), None).unwrap();
+
+        let script = r#"
+            'quickjs' + 'wasm'
+        "#;
+
+        let result = quickjs.try_execute(script, None).unwrap();
+
+        assert_eq!(result, Some("\"quickjswasm\"".to_string()));
+    }
+
+    #[test]
+    fn try_execute_memory_limit_exceed() {
+        let quickjs = QuickJS::try_new(None, false, false, Some(4194304), None).unwrap();
+
+        let script = r#"
+            let memory = [];
+            while (true) {
+                memory.push("allocate");
+            }
+        "#;
 
         match quickjs.try_execute(script, None) {
-            Err(err) if err.root_cause().to_string().contains("exceeds time limit") => {}
+            Err(ExecutionError::OutOfMemory) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn try_execute_time_limit() {
+        let quickjs = QuickJS::try_new(
+            None,
+            false,
+            false,
+            None,
+            Some(TimeLimit::new(Duration::from_secs(2))),
+        )
+        .unwrap();
+
+        let script = r#"
+            function sleep(milliseconds) {
+                const date = Date.now();
+                let currentDate = null;
+                do {
+                    currentDate = Date.now();
+                } while (currentDate - date < milliseconds);
+            }
+            sleep(30000);
+        "#;
+
+        match quickjs.try_execute(script, None) {
+            Err(ExecutionError::Timeout) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn builder_try_execute() {
+        let quickjs = QuickJS::builder()
+            .memory_limit(4194304)
+            .time_limit(TimeLimit::new(Duration::from_secs(2)))
+            .build()
+            .unwrap();
+
+        let result = quickjs.try_execute("'quickjs' + 'wasm'", None).unwrap();
+
+        assert_eq!(result, Some("\"quickjswasm\"".to_string()));
+    }
+
+    #[test]
+    fn builder_rejects_invalid_limits() {
+        match QuickJS::builder().memory_limit(PAGE_SIZE - 1).build() {
+            Err(err) if err.to_string().contains("smaller than one wasm page") => {}
+            other => panic!("{:?}", other),
+        }
+
+        let time_limit =
+            TimeLimit::new(Duration::from_secs(1)).with_evaluation_interval(Duration::ZERO);
+        match QuickJS::builder().time_limit(time_limit).build() {
+            Err(err) if err.to_string().contains("evaluation_interval") => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn pooling_allocator() {
+        let quickjs = QuickJS::builder()
+            .memory_limit(16777216)
+            .pooling_allocator(2)
+            .build()
+            .unwrap();
+
+        for _ in 0..4 {
+            let result = quickjs.try_execute("'quickjs' + 'wasm'", None).unwrap();
+            assert_eq!(result, Some("\"quickjswasm\"".to_string()));
+        }
+
+        match QuickJS::builder().pooling_allocator(2).build() {
+            Err(err) if err.to_string().contains("requires a memory limit") => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn cache_dir() {
+        let cache_dir = std::env::temp_dir().join(format!("quickjs-cache-{}", std::process::id()));
+
+        for _ in 0..2 {
+            let quickjs = QuickJS::builder().cache_dir(&cache_dir).build().unwrap();
+            let result = quickjs.try_execute("'quickjs' + 'wasm'", None).unwrap();
+            assert_eq!(result, Some("\"quickjswasm\"".to_string()));
+        }
+
+        let artifacts = std::fs::read_dir(&cache_dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "cwasm"))
+            .count();
+        assert_eq!(artifacts, 1);
+
+        // concurrent builds write their own temporary artifact
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| QuickJS::builder().cache_dir(&cache_dir).build().unwrap());
+            }
+        });
+        let entries = std::fs::read_dir(&cache_dir).unwrap().count();
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+        assert_eq!(entries, 1);
+
+        // a cache that cannot be written falls back to the compiled module
+        let file = std::env::temp_dir().join(format!("quickjs-cache-file-{}", std::process::id()));
+        std::fs::write(&file, "").unwrap();
+        let quickjs = QuickJS::builder().cache_dir(file.join("cache")).build();
+        std::fs::remove_file(&file).unwrap();
+        let result = quickjs.unwrap().try_execute("1 + 1", None).unwrap();
+        assert_eq!(result, Some("2".to_string()));
+    }
+
+    #[cfg(feature = "precompiled")]
+    #[test]
+    fn precompiled_rejects_mismatched_engine() {
+        match QuickJS::builder().fuel_limit(FuelLimit::new(1000)).build() {
+            Err(err) if err.to_string().contains("fuel metering") => {}
+            other => panic!("{:?}", other),
+        }
+        match QuickJS::builder().async_support(true).build() {
+            Err(err) if err.to_string().contains("async support") => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn try_execute_typed() {
+        #[derive(Serialize)]
+        struct Input {
+            values: Vec<f64>,
+        }
+
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Output {
+            sum: f64,
+            count: usize,
+        }
+
+        let quickjs = QuickJS::builder().build().unwrap();
+
+        let script = r#"
+            ({ sum: data.values.reduce((a, b) => a + b, 0), count: data.values.length })
+        "#;
+
+        let output: Output = quickjs
+            .try_execute_typed(
+                script,
+                &Input {
+                    values: vec![1.0, 2.0, 3.5],
+                },
+            )
+            .unwrap();
+        assert_eq!(output, Output { sum: 6.5, count: 3 });
+
+        let output = quickjs
+            .try_execute_value(
+                "data.input.toUpperCase()",
+                Some(&serde_json::json!({ "input": "wasm" })),
+            )
+            .unwrap();
+        assert_eq!(output, Value::String("WASM".to_string()));
+
+        let output = quickjs.try_execute_value("undefined", None).unwrap();
+        assert_eq!(output, Value::Null);
+    }
+
+    #[test]
+    fn call() {
+        let quickjs = QuickJS::builder().build().unwrap();
+
+        let script = r#"
+            function transform(value, suffix) {
+                return value.toUpperCase() + suffix;
+            }
+            const notFunction = 1;
+        "#;
+
+        let result = quickjs
+            .call(
+                script,
+                "transform",
+                &[Value::from("quickjs"), Value::from("wasm")],
+            )
+            .unwrap();
+        assert_eq!(result, Value::from("QUICKJSwasm"));
+
+        let result = quickjs
+            .call(
+                "({ add: (a, b) => a + b })",
+                "add",
+                &[Value::from(1), Value::from(2)],
+            )
+            .unwrap();
+        assert_eq!(result, Value::from(3));
+
+        match quickjs.call(script, "missing", &[]) {
+            Err(ExecutionError::FunctionNotFound(name)) if name == "missing" => {}
+            other => panic!("{:?}", other),
+        }
+
+        match quickjs.call(script, "notFunction", &[]) {
+            Err(ExecutionError::NotCallable(name)) if name == "notFunction" => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn host_fn() {
+        let quickjs = QuickJS::builder()
+            .host_fn("lookupUser", |args: Value| {
+                Ok(serde_json::json!({ "id": args[0], "name": "quickjs" }))
+            })
+            .host_fn("fail", |_| Err(anyhow!("lookup failed")))
+            .build()
+            .unwrap();
+
+        let result = quickjs
+            .try_execute_value("host.lookupUser(42)", None)
+            .unwrap();
+        assert_eq!(result, serde_json::json!({ "id": 42, "name": "quickjs" }));
+
+        let script = r#"
+            try {
+                host.fail();
+            } catch (e) {
+                e.message
+            }
+        "#;
+        let result = quickjs.try_execute_value(script, None).unwrap();
+        assert!(result.as_str().unwrap().contains("lookup failed"));
+
+        // names are validated like the names of inputs
+        for name in ["__proto__", "not-an-identifier"] {
+            let builder = QuickJS::builder().host_fn(name, |args: Value| Ok(args));
+            assert!(builder.build().is_err());
+        }
+    }
+
+    #[test]
+    fn try_execute_with_output() {
+        let quickjs = QuickJS::builder()
+            .stdout(Stdio::Capture { limit: 1024 })
+            .stderr(Stdio::Capture { limit: 8 })
+            .build()
+            .unwrap();
+
+        let script = r#"
+            console.log('quickjs', 'wasm');
+            console.error('truncated output');
+            1 + 1
+        "#;
+
+        let mut output = ExecutionOutput::default();
+        let result = quickjs
+            .with_options(ExecutionOptions::new().output(&mut output))
+            .try_execute(script, None)
+            .unwrap();
+        assert_eq!(result, Some("2".to_string()));
+        assert_eq!(
+            output,
+            ExecutionOutput {
+                stdout: "quickjs wasm\n".to_string(),
+                stderr: "truncate".to_string(),
+                fuel_consumed: None,
+            }
+        );
+
+        // every entry point accepts the options
+        let mut output = ExecutionOutput::default();
+        let result = quickjs
+            .with_options(ExecutionOptions::new().output(&mut output))
+            .call(
+                "function log(value) { console.log(value); return value }",
+                "log",
+                &[Value::from("called")],
+            )
+            .unwrap();
+        assert_eq!(result, Value::from("called"));
+        assert_eq!(output.stdout, "called\n");
+
+        let mut session = quickjs.session().unwrap();
+        let mut output = ExecutionOutput::default();
+        session
+            .with_options(ExecutionOptions::new().output(&mut output))
+            .eval("console.log('session')", None)
+            .unwrap();
+        assert_eq!(output.stdout, "session\n");
+        assert!(session.take_stdout().is_empty());
+    }
+
+    #[test]
+    fn try_execute_fuel_limit() {
+        let quickjs = QuickJS::builder()
+            .fuel_limit(FuelLimit::new(100_000_000))
+            .build()
+            .unwrap();
+
+        let script = r#"
+            let sum = 0;
+            for (let i = 0; i < 1000; i++) {
+                sum += i;
+            }
+            sum
+        "#;
+
+        let mut first = ExecutionOutput::default();
+        let mut second = ExecutionOutput::default();
+        let result = quickjs
+            .with_options(ExecutionOptions::new().output(&mut first))
+            .try_execute(script, None)
+            .unwrap();
+        quickjs
+            .with_options(ExecutionOptions::new().output(&mut second))
+            .try_execute(script, None)
+            .unwrap();
+        assert_eq!(result, Some("499500".to_string()));
+        assert!(first.fuel_consumed.unwrap() > 0);
+        assert_eq!(first.fuel_consumed, second.fuel_consumed);
+
+        match quickjs.try_execute("while (true) {}", None) {
+            Err(ExecutionError::OutOfFuel) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn try_execute_cancellable() {
+        let quickjs = QuickJS::builder().cancellable(true).build().unwrap();
+
+        let handle = quickjs.execution_handle();
+        let canceller = {
+            let handle = handle.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(100));
+                handle.cancel();
+            })
+        };
+
+        let cancellable = |handle| quickjs.with_options(ExecutionOptions::new().handle(handle));
+        match cancellable(&handle).try_execute("while (true) {}", None) {
+            Err(ExecutionError::Cancelled) => {}
+            other => panic!("{:?}", other),
+        }
+        canceller.join().unwrap();
+
+        // a cancelled handle fails immediately while a new one runs to completion
+        assert!(matches!(
+            cancellable(&handle).try_execute("1 + 1", None),
+            Err(ExecutionError::Cancelled)
+        ));
+        assert!(matches!(
+            cancellable(&handle).call("", "Math.max", &[]),
+            Err(ExecutionError::Cancelled)
+        ));
+        let result = cancellable(&quickjs.execution_handle()).try_execute("1 + 1", None);
+        assert_eq!(result.unwrap(), Some("2".to_string()));
+    }
+
+    #[test]
+    fn time_limit_ticker() {
+        // each ticker is stopped and joined when its QuickJS is dropped
+        for _ in 0..16 {
+            let quickjs = QuickJS::builder()
+                .time_limit(TimeLimit::new(Duration::from_millis(100)))
+                .build()
+                .unwrap();
+            assert_eq!(
+                quickjs.try_execute("1", None).unwrap(),
+                Some("1".to_string())
+            );
+        }
+
+        let quickjs = QuickJS::builder()
+            .time_limit(TimeLimit::new(Duration::from_millis(100)))
+            .build()
+            .unwrap();
+
+        // the ticker sleeps between executions without shortening the next deadline
+        std::thread::sleep(Duration::from_millis(200));
+        let started = Instant::now();
+        match quickjs.try_execute("while (true) {}", None) {
+            Err(ExecutionError::Timeout) => {}
+            other => panic!("{:?}", other),
+        }
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn try_execute_async() {
+        let quickjs = QuickJS::builder()
+            .async_support(true)
+            .cancellable(true)
+            .time_limit(TimeLimit::new(Duration::from_secs(1)))
+            .build()
+            .unwrap();
+
+        let result = quickjs.try_execute_async("1 + 1", None).await;
+        assert_eq!(result.unwrap(), Some("2".to_string()));
+
+        // a long script yields so other tasks make progress on the same thread
+        let handle = quickjs.execution_handle();
+        let canceller = {
+            let handle = handle.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                handle.cancel();
+            }
+        };
+        let (result, _) = tokio::join!(
+            quickjs
+                .with_options(ExecutionOptions::new().handle(&handle))
+                .try_execute_async("while (true) {}", None),
+            canceller
+        );
+        assert!(matches!(result, Err(ExecutionError::Cancelled)));
+
+        // a script waiting for a timer is cancelled as well
+        let handle = quickjs.execution_handle();
+        let canceller = {
+            let handle = handle.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                handle.cancel();
+            }
+        };
+        let (result, _) = tokio::join!(
+            quickjs
+                .with_options(ExecutionOptions::new().handle(&handle))
+                .try_execute_async("setTimeout(() => {}, 900)", None),
+            canceller
+        );
+        assert!(matches!(result, Err(ExecutionError::Cancelled)));
+
+        match quickjs.try_execute_async("while (true) {}", None).await {
+            Err(ExecutionError::Timeout) => {}
+            other => panic!("{:?}", other),
+        }
+
+        // the blocking api is unavailable on an async engine
+        assert!(quickjs.try_execute("1 + 1", None).is_err());
+    }
+
+    #[test]
+    fn try_execute_promise() {
+        let quickjs = QuickJS::builder().build().unwrap();
+
+        let script = r#"
+            async function double(value) {
+                await null;
+                return value * 2;
+            }
+            double(data.value)
+        "#;
+        let result = quickjs.try_execute(script, Some(r#"{ "value": 21 }"#));
+        assert_eq!(result.unwrap(), Some("42".to_string()));
+
+        // queued jobs run before the output is read
+        let script = r#"
+            const state = { resolved: false };
+            Promise.resolve().then(() => { state.resolved = true });
+            state
+        "#;
+        let result = quickjs.try_execute(script, None);
+        assert_eq!(result.unwrap(), Some(r#"{"resolved":true}"#.to_string()));
+
+        match quickjs.try_execute("Promise.reject(new TypeError('rejected'))", None) {
+            Err(ExecutionError::JsException { name, message, .. }) => {
+                assert_eq!(name, "TypeError");
+                assert_eq!(message, "rejected");
+            }
+            other => panic!("{:?}", other),
+        }
+
+        // top-level await evaluates the script as a module, its result is the default export
+        let script = r#"
+            const value = await Promise.resolve(data.value);
+            export default value * 2;
+        "#;
+        let result = quickjs.try_execute(script, Some(r#"{ "value": 2 }"#));
+        assert_eq!(result.unwrap(), Some("4".to_string()));
+
+        // it also compiles to bytecode as a module
+        let script = quickjs
+            .compile("export default await Promise.resolve('awaited');")
+            .unwrap();
+        let result = quickjs.try_execute_compiled(&script, None);
+        assert_eq!(result.unwrap(), Some(r#""awaited""#.to_string()));
+
+        // a module without a default export results in undefined
+        let result = quickjs.try_execute_value("await null;", None);
+        assert_eq!(result.unwrap(), Value::Null);
+
+        // its bindings are scoped to the module instead of the global object
+        let result = quickjs.try_execute(
+            "var count = await 1; export default typeof globalThis.count;",
+            None,
+        );
+        assert_eq!(result.unwrap(), Some(r#""undefined""#.to_string()));
+
+        // `call` finds the exports of a module once its top-level await settled
+        let script = r#"
+            const factor = await Promise.resolve(3);
+            export function scale(value) {
+                return value * factor;
+            }
+            const hidden = () => factor;
+        "#;
+        let result = quickjs.call(script, "scale", &[serde_json::json!(2)]);
+        assert_eq!(result.unwrap(), serde_json::json!(6));
+        match quickjs.call(script, "hidden", &[]) {
+            Err(ExecutionError::FunctionNotFound(name)) if name == "hidden" => {}
+            other => panic!("{:?}", other),
+        }
+
+        // a script that parses neither way keeps the syntax error of the global script
+        match quickjs.try_execute("const value = ;", None) {
+            Err(ExecutionError::JsException { name, .. }) => assert_eq!(name, "SyntaxError"),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn try_execute_timers() {
+        let script = r#"
+            const events = [];
+            const interval = setInterval(() => {
+                events.push("interval");
+                if (events.length === 3) clearInterval(interval);
+            }, 1000);
+            setTimeout((name) => events.push(name), 1500, "timeout");
+            clearTimeout(setTimeout(() => events.push("cleared"), 10));
+            new Promise((resolve) => setTimeout(() => resolve(events), 5000))
+        "#;
+
+        // virtual time fast-forwards through the 5s of timers
+        let quickjs = QuickJS::builder()
+            .timers(TimerMode::Virtual)
+            .time_limit(TimeLimit::new(Duration::from_secs(1)))
+            .build()
+            .unwrap();
+        let result = quickjs.try_execute(script, None).unwrap();
+        assert_eq!(
+            result,
+            Some(r#"["interval","timeout","interval","interval"]"#.to_string())
+        );
+
+        // a real time timer after the time limit fails without waiting for it
+        let quickjs = QuickJS::builder()
+            .time_limit(TimeLimit::new(Duration::from_millis(100)))
+            .build()
+            .unwrap();
+        assert!(matches!(
+            quickjs.try_execute("setTimeout(() => {}, 60000)", None),
+            Err(ExecutionError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn cancel_timer_wait() {
+        let quickjs = QuickJS::builder().cancellable(true).build().unwrap();
+
+        let handle = quickjs.execution_handle();
+        let canceller = {
+            let handle = handle.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(100));
+                handle.cancel();
+            })
+        };
+
+        // the host stops waiting for the timer instead of sleeping for a minute
+        let started = Instant::now();
+        match quickjs
+            .with_options(ExecutionOptions::new().handle(&handle))
+            .try_execute("setTimeout(() => {}, 60000)", None)
+        {
+            Err(ExecutionError::Cancelled) => {}
+            other => panic!("{:?}", other),
+        }
+        assert!(started.elapsed() < Duration::from_secs(5));
+        canceller.join().unwrap();
+    }
+
+    #[test]
+    fn try_execute_module() {
+        let quickjs = QuickJS::builder().build().unwrap();
+
+        let modules = Arc::new(std::collections::HashMap::from([
+            (
+                "main.js".to_string(),
+                r#"
+                    import { greet } from "./lib/greet.js";
+                    const lib = "./lib/name.js";
+                    export const lazy = import(lib).then(({ name }) => greet(name));
+                    export default greet(data.name);
+                "#
+                .to_string(),
+            ),
+            (
+                "lib/greet.js".to_string(),
+                r#"
+                    import { suffix } from "./suffix.js";
+                    export const greet = (name) => `hello ${name}${suffix()}`;
+                "#
+                .to_string(),
+            ),
+            // circular imports resolve like they do in any ES module loader
+            (
+                "lib/suffix.js".to_string(),
+                r#"
+                    import { greet } from "./greet.js";
+                    export const suffix = () => (typeof greet === "function" ? "!" : "?");
+                "#
+                .to_string(),
+            ),
+            (
+                "lib/name.js".to_string(),
+                r#"export const name = "module";"#.to_string(),
+            ),
+        ]));
+
+        let result = quickjs.try_execute_module(
+            "main.js",
+            "default",
+            modules.clone(),
+            Some(r#"{ "name": "quickjs" }"#),
+        );
+        assert_eq!(result.unwrap(), Some(r#""hello quickjs!""#.to_string()));
+
+        let result = quickjs.try_execute_module("./main.js", "lazy", modules.clone(), Some("{}"));
+        assert_eq!(result.unwrap(), Some(r#""hello module!""#.to_string()));
+
+        match quickjs.try_execute_module("missing.js", "default", modules.clone(), None) {
+            Err(ExecutionError::ModuleNotFound(name)) => assert_eq!(name, "missing.js"),
+            other => panic!("{:?}", other),
+        }
+
+        // a failed import can be caught by the importing module
+        let modules = Arc::new(std::collections::HashMap::from([
+            (
+                "lib/optional.js".to_string(),
+                r#"
+                    export default await import("./missing.js").then(
+                        () => "loaded",
+                        (e) => `${e.name}: ${e.message}`,
+                    );
+                "#
+                .to_string(),
+            ),
+            (
+                "lib/static.js".to_string(),
+                r#"import { value } from "../other/missing.js";"#.to_string(),
+            ),
+        ]));
+        let result =
+            quickjs.try_execute_module("lib/optional.js", "default", modules.clone(), None);
+        assert_eq!(
+            result.unwrap(),
+            Some(
+                r#""ModuleNotFoundError: cannot find module \"lib/missing.js\" imported by \"lib/optional.js\"""#
+                    .to_string()
+            )
+        );
+        match quickjs.try_execute_module("lib/static.js", "default", modules, None) {
+            Err(ExecutionError::ModuleNotFound(name)) => assert_eq!(name, "other/missing.js"),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn try_execute_compiled() {
+        let quickjs = QuickJS::builder().build().unwrap();
+
+        let script = quickjs
+            .compile_named("transform.js", "data.value * 2")
+            .unwrap();
+        assert_eq!(script.name(), "transform.js");
+
+        let shared = script.clone();
+        let result = std::thread::spawn(move || {
+            let quickjs = QuickJS::builder().build().unwrap();
+            quickjs.try_execute_compiled(&shared, Some(r#"{ "value": 21 }"#))
+        })
+        .join()
+        .unwrap();
+        assert_eq!(result.unwrap(), Some("42".to_string()));
+
+        match quickjs.try_execute_compiled(
+            &quickjs.compile("throw new Error('compiled')").unwrap(),
+            None,
+        ) {
+            Err(ExecutionError::JsException { message, stack, .. }) => {
+                assert_eq!(message, "compiled");
+                assert!(stack.unwrap().contains("script.js"));
+            }
+            other => panic!("{:?}", other),
+        }
+
+        match quickjs.compile("let 1 = 2") {
+            Err(ExecutionError::JsException { name, .. }) => assert_eq!(name, "SyntaxError"),
+            other => panic!("{:?}", other),
+        }
+
+        // an empty custom section changes the module but not its behavior
+        let mut wasm = std::fs::read("../../quickjs.wasm").unwrap();
+        wasm.extend_from_slice(&[0, 5, 4, b't', b'e', b's', b't']);
+        let other = QuickJS::builder().module_bytes(wasm).build().unwrap();
+        match other.try_execute_compiled(&script, Some(r#"{ "value": 21 }"#)) {
+            Err(ExecutionError::InvalidInput(_)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[cfg(feature = "wizer")]
+    #[test]
+    fn specialize() {
+        let quickjs = QuickJS::builder()
+            .specialize(
+                r#"
+                const table = new Map([["a", 1], ["b", 2]]);
+                let calls = 0;
+                function lookup(key) {
+                    calls += 1;
+                    return [table.get(key), calls];
+                }
+            "#,
+            )
+            .build()
+            .unwrap();
+
+        // every execution starts from the snapshot taken after the script ran
+        for _ in 0..2 {
+            let result = quickjs.call("", "lookup", &[Value::from("b")]).unwrap();
+            assert_eq!(result, serde_json::json!([2, 1]));
+        }
+
+        match QuickJS::builder()
+            .specialize("throw new RangeError('init')")
+            .build()
+        {
+            Err(err) => match err.downcast::<ExecutionError>() {
+                Ok(ExecutionError::JsException { name, .. }) => assert_eq!(name, "RangeError"),
+                other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn session() {
+        let quickjs = QuickJS::builder()
+            .time_limit(TimeLimit::new(Duration::from_millis(200)))
+            .build()
+            .unwrap();
+        let mut session = quickjs.session().unwrap();
+
+        session
+            .eval(
+                "var count = 0; function add(by) { return count += by; }",
+                None,
+            )
+            .unwrap();
+        session
+            .eval("count += data.by", Some(r#"{"by": 2}"#))
+            .unwrap();
+        assert_eq!(
+            session.call("add", &[serde_json::json!(3)]).unwrap(),
+            serde_json::json!(5)
+        );
+
+        // the time limit applies to each call, not the whole session
+        std::thread::sleep(Duration::from_millis(300));
+        assert_eq!(session.eval("count", None).unwrap(), Some("5".to_string()));
+        // data is not carried over from a previous call
+        assert_eq!(
+            session.eval("typeof data", None).unwrap(),
+            Some(r#""undefined""#.to_string())
+        );
+        // not even when a script assigns it
+        session.eval("var data = 1", None).unwrap();
+        assert_eq!(
+            session.eval("typeof data", None).unwrap(),
+            Some(r#""undefined""#.to_string())
+        );
+
+        match session.eval("while (true) {}", None) {
+            Err(ExecutionError::Timeout) => {}
+            other => panic!("{:?}", other),
+        }
+        assert!(session.is_poisoned());
+        assert!(session.eval("count", None).is_err());
+    }
+
+    #[test]
+    fn session_snapshot() {
+        let quickjs = QuickJS::builder().build().unwrap();
+        let mut session = quickjs.session().unwrap();
+        session
+            .eval(
+                "var count = 0; function add(by) { return count += by; }",
+                None,
+            )
+            .unwrap();
+        session.call("add", &[serde_json::json!(2)]).unwrap();
+
+        let mut snapshot = session.snapshot().unwrap();
+        session.call("add", &[serde_json::json!(1)]).unwrap();
+
+        // the restored session forks from the state at the snapshot
+        let mut restored = quickjs.restore(&snapshot).unwrap();
+        assert_eq!(
+            restored.call("add", &[serde_json::json!(5)]).unwrap(),
+            serde_json::json!(7)
+        );
+        assert_eq!(session.eval("count", None).unwrap(), Some("3".to_string()));
+
+        // the module hash follows the magic and version
+        snapshot[8] ^= 0xff;
+        match quickjs.restore(&snapshot) {
+            Err(ExecutionError::InvalidInput(_)) => {}
+            other => panic!("{:?}", other),
+        };
+    }
+
+    #[test]
+    fn try_execute_message_pack() {
+        #[derive(Serialize)]
+        struct Input {
+            values: Vec<f64>,
+        }
+
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Output {
+            sum: f64,
+            count: usize,
+        }
+
+        let quickjs = QuickJS::builder()
+            .wire_format(WireFormat::MessagePack)
+            .build()
+            .unwrap();
+
+        let script = r#"
+            ({ sum: data.values.reduce((a, b) => a + b, 0), count: data.values.length })
+        "#;
+        let input = Input {
+            values: vec![1.0, 2.0, 3.5],
+        };
+
+        let output: Output = quickjs.try_execute_typed(script, &input).unwrap();
+        assert_eq!(output, Output { sum: 6.5, count: 3 });
+
+        let output = quickjs
+            .try_execute_bytes(script, Some(&rmp_serde::to_vec_named(&input).unwrap()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            rmp_serde::from_slice::<Output>(&output).unwrap(),
+            Output { sum: 6.5, count: 3 }
+        );
+
+        // the string methods stay JSON
+        assert_eq!(
+            quickjs.try_execute("data.a", Some(r#"{"a": 1}"#)).unwrap(),
+            Some("1".to_string())
+        );
+    }
+
+    #[test]
+    fn try_execute_cbor() {
+        use ciborium::value::{Integer, Value as Cbor};
+
+        let quickjs = QuickJS::builder()
+            .wire_format(WireFormat::Cbor)
+            .build()
+            .unwrap();
+
+        let input = Cbor::Map(vec![
+            (
+                Cbor::Text("at".to_string()),
+                Cbor::Tag(1, Box::new(Cbor::Integer(Integer::from(1700000000)))),
+            ),
+            (Cbor::Text("blob".to_string()), Cbor::Bytes(vec![1, 2, 3])),
+            (
+                Cbor::Text("big".to_string()),
+                Cbor::Integer(Integer::from(u64::MAX)),
+            ),
+            (
+                Cbor::Text("safe".to_string()),
+                Cbor::Integer(Integer::from((1_i64 << 53) - 1)),
+            ),
+            (
+                Cbor::Text("unsafe".to_string()),
+                Cbor::Integer(Integer::from(1_i64 << 53)),
+            ),
+        ]);
+        let script = r#"({
+            year: data.at.getUTCFullYear(),
+            typed: data.blob instanceof Uint8Array,
+            at: data.at,
+            blob: data.blob.subarray(1),
+            big: data.big - 1n,
+            types: [typeof data.safe, typeof data.unsafe],
+            whole: 2 ** 40,
+            fraction: 0.5,
+        })"#;
+
+        let output: Cbor = quickjs.try_execute_typed(script, &input).unwrap();
+        assert_eq!(
+            output,
+            Cbor::Map(vec![
+                (
+                    Cbor::Text("year".to_string()),
+                    Cbor::Integer(Integer::from(2023))
+                ),
+                (Cbor::Text("typed".to_string()), Cbor::Bool(true)),
+                (
+                    Cbor::Text("at".to_string()),
+                    Cbor::Tag(1, Box::new(Cbor::Integer(Integer::from(1700000000))))
+                ),
+                (Cbor::Text("blob".to_string()), Cbor::Bytes(vec![2, 3])),
+                (
+                    Cbor::Text("big".to_string()),
+                    Cbor::Integer(Integer::from(u64::MAX - 1))
+                ),
+                (
+                    Cbor::Text("types".to_string()),
+                    Cbor::Array(vec![
+                        Cbor::Text("number".to_string()),
+                        Cbor::Text("bigint".to_string())
+                    ])
+                ),
+                (
+                    Cbor::Text("whole".to_string()),
+                    Cbor::Integer(Integer::from(1_i64 << 40))
+                ),
+                (Cbor::Text("fraction".to_string()), Cbor::Float(0.5)),
+            ])
+        );
+    }
+
+    #[test]
+    fn try_execute_binary() {
+        let quickjs = QuickJS::builder().build().unwrap();
+
+        let output = quickjs
+            .try_execute_binary("data.map((byte) => byte * 2).subarray(1)", &[1, 2, 3])
+            .unwrap();
+        assert_eq!(output, vec![4, 6]);
+
+        let output = quickjs
+            .try_execute_binary(
+                "const view = new DataView(new ArrayBuffer(2)); view.setUint16(0, data.length); view",
+                &[],
+            )
+            .unwrap();
+        assert_eq!(output, vec![0, 0]);
+
+        let output = quickjs
+            .try_execute_binary("new DataView(data.buffer, 1, 2)", &[1, 2, 3])
+            .unwrap();
+        assert_eq!(output, vec![2, 3]);
+
+        match quickjs.try_execute_binary("'text'", &[1]) {
+            Err(ExecutionError::JsException { name, .. }) => assert_eq!(name, "TypeError"),
+            other => panic!("{:?}", other),
+        }
+
+        // only the slots of an actual view are read
+        let script = "({ buffer: data.buffer, byteOffset: 2, byteLength: 4 })";
+        match quickjs.try_execute_binary(script, &[1, 2, 3]) {
+            Err(ExecutionError::JsException { name, .. }) => assert_eq!(name, "TypeError"),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn try_execute_inputs() {
+        let quickjs = QuickJS::builder().build().unwrap();
+
+        let mut inputs = Inputs::new();
+        inputs
+            .insert("request", &serde_json::json!({ "id": 7 }))
+            .unwrap()
+            .insert_frozen("config", &serde_json::json!({ "limit": 10 }))
+            .unwrap();
+
+        let script = r#"
+            "use strict";
+            let frozen = false;
+            try {
+                config.limit = 0;
+            } catch (err) {
+                frozen = err instanceof TypeError;
+            }
+            ({ allowed: request.id < config.limit, frozen })
+        "#;
+        assert_eq!(
+            quickjs.try_execute_inputs(script, &inputs).unwrap(),
+            Some(r#"{"allowed":true,"frozen":true}"#.to_string())
+        );
+
+        let result = quickjs
+            .call_inputs(
+                "function check(request, config) { return Object.isFrozen(config) && request.id; }",
+                "check",
+                &inputs,
+            )
+            .unwrap();
+        assert_eq!(result, serde_json::json!(7));
+
+        // builtins can be shadowed without breaking the runtime
+        let mut builtins = Inputs::new();
+        builtins
+            .insert_frozen("Object", &serde_json::json!({ "id": 1 }))
+            .unwrap()
+            .insert("Promise", &2)
+            .unwrap();
+        assert_eq!(
+            quickjs
+                .try_execute_inputs("(async () => Object.id + Promise)()", &builtins)
+                .unwrap(),
+            Some("3".to_string())
+        );
+
+        for name in ["let", "host", "__proto__", "not-an-identifier"] {
+            match Inputs::new().insert(name, &1) {
+                Err(ExecutionError::InvalidInput(_)) => {}
+                other => panic!("{:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn try_execute_lazy() {
+        use std::sync::Mutex;
+
+        // records the paths read by the script
+        struct Recorder(Value, Mutex<Vec<Vec<PathSegment>>>);
+
+        impl DataProvider for Recorder {
+            fn get(&self, path: &[PathSegment]) -> Option<LazyValue> {
+                self.1.lock().unwrap().push(path.to_vec());
+                DataProvider::get(&self.0, path)
+            }
+        }
+
+        let quickjs = QuickJS::builder().build().unwrap();
+
+        let data = serde_json::json!({
+            "users": [{ "name": "ada", "age": 36 }, { "name": "alan", "age": 41 }],
+            "large": (0..10000).collect::<Vec<_>>(),
+        });
+        let provider = Arc::new(Recorder(data, Mutex::default()));
+
+        let script = r#"
+            const enumerated = [];
+            for (const key in data) enumerated.push(key);
+            ({
+                names: data.users.map((user) => user.name),
+                isArray: Array.isArray(data.users),
+                missing: data.missing,
+                keys: Object.keys(data),
+                enumerated,
+                user: { ...data.users[0] },
+            })
+        "#;
+        assert_eq!(
+            quickjs.try_execute_lazy(script, provider.clone()).unwrap(),
+            Some(
+                r#"{"names":["ada","alan"],"isArray":true,"keys":["large","users"],"enumerated":["large","users"],"user":{"age":36,"name":"ada"}}"#
+                    .to_string()
+            )
+        );
+        // nothing of `large` was read
+        assert!(provider
+            .1
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|path| path.first() != Some(&PathSegment::Key("large".to_string()))));
+
+        match quickjs.try_execute_lazy("data.users = []", provider) {
+            Err(ExecutionError::JsException { name, .. }) => assert_eq!(name, "TypeError"),
             other => panic!("{:?}", other),
         }
     }
@@ -0,0 +1,90 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::BuildHasher,
+};
+
+/// Supplies the source of the ES modules run by
+/// [`QuickJS::try_execute_module`](crate::QuickJS::try_execute_module).
+///
+/// Modules are requested by their normalized name when the guest first imports them, relative
+/// specifiers (`./` and `../`) are resolved against the name of the importing module the same way
+/// quickjs does i.e. `./util.js` imported by `lib/main.js` is requested as `lib/util.js`.
+pub trait ModuleResolver: Send + Sync {
+    /// the source of the module `name` or `None` if it does not exist
+    fn resolve(&self, name: &str) -> Option<String>;
+}
+
+impl<S: BuildHasher + Send + Sync> ModuleResolver for HashMap<String, String, S> {
+    fn resolve(&self, name: &str) -> Option<String> {
+        self.get(name).cloned()
+    }
+}
+
+impl ModuleResolver for BTreeMap<String, String> {
+    fn resolve(&self, name: &str) -> Option<String> {
+        self.get(name).cloned()
+    }
+}
+
+/// The entry module of an execution passed to the guest, which imports it and every module it
+/// imports from the host on demand.
+#[derive(Serialize)]
+pub(crate) struct EntryModule {
+    name: String,
+    export: String,
+}
+
+impl EntryModule {
+    pub fn new(entry: &str, export: &str) -> Self {
+        Self {
+            name: normalize("", entry),
+            export: export.to_string(),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+}
+
+/// resolve `specifier` imported by the module `base` like quickjs' default module name
+/// normalization, which only normalizes the leading `./` and `../`
+fn normalize(base: &str, specifier: &str) -> String {
+    if !specifier.starts_with('.') {
+        return specifier.to_string();
+    }
+
+    let mut dir = match base.rfind('/') {
+        Some(index) => base[..index].to_string(),
+        None => String::new(),
+    };
+    let mut rest = specifier;
+    loop {
+        if let Some(stripped) = rest.strip_prefix("./") {
+            rest = stripped;
+        } else if let Some(stripped) = rest.strip_prefix("../") {
+            if dir.is_empty() {
+                break;
+            }
+            let (parent, last) = match dir.rfind('/') {
+                Some(index) => (&dir[..index], &dir[index + 1..]),
+                None => ("", dir.as_str()),
+            };
+            if last == "." || last == ".." {
+                break;
+            }
+            dir = parent.to_string();
+            rest = stripped;
+        } else {
+            break;
+        }
+    }
+
+    if dir.is_empty() {
+        rest.to_string()
+    } else {
+        format!("{dir}/{rest}")
+    }
+}
@@ -0,0 +1,405 @@
+use crate::{
+    decode_output, modules::EntryModule, CompiledScript, DataProvider, ExecutionError,
+    ExecutionHandle, Inputs, Invocation, ModuleResolver, QuickJS, WireFormat,
+};
+use anyhow::anyhow;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Options of a single execution, accepted by every entry point through
+/// [`QuickJS::with_options`] and [`Session::with_options`](crate::Session::with_options).
+///
+/// ```no_run
+/// use quickjs::{ExecutionOptions, ExecutionOutput, QuickJS, Stdio};
+///
+/// let quickjs = QuickJS::builder()
+///     .stdout(Stdio::Capture { limit: 1024 })
+///     .cancellable(true)
+///     .build()
+///     .unwrap();
+/// let handle = quickjs.execution_handle();
+/// let mut output = ExecutionOutput::default();
+///
+/// let options = ExecutionOptions::new().handle(&handle).output(&mut output);
+/// quickjs.with_options(options).try_execute("console.log('hi')", None).unwrap();
+/// assert_eq!(output.stdout, "hi\n");
+/// ```
+#[derive(Debug, Default)]
+pub struct ExecutionOptions<'a> {
+    pub(crate) handle: Option<&'a ExecutionHandle>,
+    pub(crate) output: Option<&'a mut ExecutionOutput>,
+}
+
+impl<'a> ExecutionOptions<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// fail with [`ExecutionError::Cancelled`] once [`ExecutionHandle::cancel`] is called.
+    /// requires [`QuickJSBuilder::cancellable`](crate::QuickJSBuilder::cancellable).
+    pub fn handle(mut self, handle: &'a ExecutionHandle) -> Self {
+        self.handle = Some(handle);
+        self
+    }
+
+    /// fill `output` once the guest finished, including when the script threw
+    pub fn output(mut self, output: &'a mut ExecutionOutput) -> Self {
+        self.output = Some(output);
+        self
+    }
+}
+
+/// What an execution reported besides its result, see [`ExecutionOptions::output`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExecutionOutput {
+    /// everything written to stdout (i.e. `console.log`) when using
+    /// [`Stdio::Capture`](crate::Stdio::Capture)
+    pub stdout: String,
+    /// everything written to stderr (i.e. `console.error`) when using
+    /// [`Stdio::Capture`](crate::Stdio::Capture)
+    pub stderr: String,
+    /// the fuel consumed by the execution when using a [`FuelLimit`](crate::FuelLimit)
+    pub fuel_consumed: Option<u64>,
+}
+
+/// The entry points of a [`QuickJS`] executing with [`ExecutionOptions`], created by
+/// [`QuickJS::with_options`].
+#[derive(Debug)]
+pub struct Executor<'a> {
+    quickjs: &'a QuickJS,
+    options: ExecutionOptions<'a>,
+}
+
+impl<'a> Executor<'a> {
+    pub(crate) fn new(quickjs: &'a QuickJS, options: ExecutionOptions<'a>) -> Self {
+        Self { quickjs, options }
+    }
+
+    /// see [`QuickJS::try_execute`]
+    pub fn try_execute(
+        self,
+        script: &str,
+        data: Option<&str>,
+    ) -> Result<Option<String>, ExecutionError> {
+        let data = data
+            .map(|data| data.as_bytes().to_vec())
+            .unwrap_or_default();
+
+        let output = self
+            .quickjs
+            .execute(
+                Invocation {
+                    script,
+                    data: data.into(),
+                    ..Default::default()
+                },
+                self.options,
+            )?
+            .output()?;
+
+        decode_output(output)
+    }
+
+    /// see [`QuickJS::try_execute_async`]
+    pub async fn try_execute_async(
+        self,
+        script: &str,
+        data: Option<&str>,
+    ) -> Result<Option<String>, ExecutionError> {
+        let data = data
+            .map(|data| data.as_bytes().to_vec())
+            .unwrap_or_default();
+
+        let output = self
+            .quickjs
+            .execute_async(
+                Invocation {
+                    script,
+                    data: data.into(),
+                    ..Default::default()
+                },
+                self.options,
+            )
+            .await?
+            .output()?;
+
+        decode_output(output)
+    }
+
+    /// see [`QuickJS::compile`]
+    pub fn compile(self, script: &str) -> Result<CompiledScript, ExecutionError> {
+        self.compile_named(crate::SCRIPT_NAME, script)
+    }
+
+    /// see [`QuickJS::compile_named`]
+    pub fn compile_named(self, name: &str, script: &str) -> Result<CompiledScript, ExecutionError> {
+        let bytecode = self
+            .quickjs
+            .execute(
+                Invocation {
+                    script,
+                    compile: Some(name),
+                    ..Default::default()
+                },
+                self.options,
+            )?
+            .output()?
+            .ok_or_else(|| anyhow!("the guest returned no bytecode"))?;
+
+        Ok(CompiledScript::new(
+            name,
+            bytecode,
+            self.quickjs.module_hash,
+        ))
+    }
+
+    /// see [`QuickJS::try_execute_compiled`]
+    pub fn try_execute_compiled(
+        self,
+        script: &CompiledScript,
+        data: Option<&str>,
+    ) -> Result<Option<String>, ExecutionError> {
+        if script.module_hash() != self.quickjs.module_hash {
+            return Err(ExecutionError::InvalidInput(
+                anyhow!("the script was compiled by a different module").into(),
+            ));
+        }
+
+        let data = data
+            .map(|data| data.as_bytes().to_vec())
+            .unwrap_or_default();
+
+        let output = self
+            .quickjs
+            .execute(
+                Invocation {
+                    bytecode: Some(script.bytecode()),
+                    data: data.into(),
+                    ..Default::default()
+                },
+                self.options,
+            )?
+            .output()?;
+
+        decode_output(output)
+    }
+
+    /// see [`QuickJS::try_execute_typed`]
+    pub fn try_execute_typed<I, O>(self, script: &str, input: &I) -> Result<O, ExecutionError>
+    where
+        I: Serialize + ?Sized,
+        O: DeserializeOwned,
+    {
+        let format = self.quickjs.wire_format;
+        let data = format.serialize(input)?;
+
+        let output = self
+            .quickjs
+            .execute(
+                Invocation {
+                    script,
+                    data: data.into(),
+                    format,
+                    ..Default::default()
+                },
+                self.options,
+            )?
+            .output()?;
+
+        format.deserialize(output)
+    }
+
+    /// see [`QuickJS::try_execute_value`]
+    pub fn try_execute_value(
+        self,
+        script: &str,
+        data: Option<&Value>,
+    ) -> Result<Value, ExecutionError> {
+        match data {
+            Some(data) => self.try_execute_typed(script, data),
+            None => {
+                let format = self.quickjs.wire_format;
+                let output = self
+                    .quickjs
+                    .execute(
+                        Invocation {
+                            script,
+                            format,
+                            ..Default::default()
+                        },
+                        self.options,
+                    )?
+                    .output()?;
+
+                format.deserialize(output)
+            }
+        }
+    }
+
+    /// see [`QuickJS::try_execute_bytes`]
+    pub fn try_execute_bytes(
+        self,
+        script: &str,
+        data: Option<&[u8]>,
+    ) -> Result<Option<Vec<u8>>, ExecutionError> {
+        self.quickjs
+            .execute(
+                Invocation {
+                    script,
+                    data: data.unwrap_or_default().into(),
+                    format: self.quickjs.wire_format,
+                    ..Default::default()
+                },
+                self.options,
+            )?
+            .output()
+    }
+
+    /// see [`QuickJS::try_execute_binary`]
+    pub fn try_execute_binary(self, script: &str, data: &[u8]) -> Result<Vec<u8>, ExecutionError> {
+        let output = self
+            .quickjs
+            .execute(
+                Invocation {
+                    script,
+                    data: data.into(),
+                    binary: true,
+                    ..Default::default()
+                },
+                self.options,
+            )?
+            .output()?;
+
+        Ok(output.unwrap_or_default())
+    }
+
+    /// see [`QuickJS::call`]
+    pub fn call(
+        self,
+        script: &str,
+        function: &str,
+        args: &[Value],
+    ) -> Result<Value, ExecutionError> {
+        let format = self.quickjs.wire_format;
+        let data = format.serialize(args)?;
+
+        let output = self
+            .quickjs
+            .execute(
+                Invocation {
+                    script,
+                    data: data.into(),
+                    format,
+                    function: Some(function),
+                    ..Default::default()
+                },
+                self.options,
+            )?
+            .output()?;
+
+        format.deserialize(output)
+    }
+
+    /// see [`QuickJS::try_execute_inputs`]
+    pub fn try_execute_inputs(
+        self,
+        script: &str,
+        inputs: &Inputs,
+    ) -> Result<Option<String>, ExecutionError> {
+        let data = WireFormat::Json.serialize(inputs.values())?;
+
+        let output = self
+            .quickjs
+            .execute(
+                Invocation {
+                    script,
+                    data: data.into(),
+                    inputs: inputs.names_json()?,
+                    ..Default::default()
+                },
+                self.options,
+            )?
+            .output()?;
+
+        decode_output(output)
+    }
+
+    /// see [`QuickJS::call_inputs`]
+    pub fn call_inputs(
+        self,
+        script: &str,
+        function: &str,
+        inputs: &Inputs,
+    ) -> Result<Value, ExecutionError> {
+        let format = self.quickjs.wire_format;
+        let data = format.serialize(inputs.values())?;
+
+        let output = self
+            .quickjs
+            .execute(
+                Invocation {
+                    script,
+                    data: data.into(),
+                    format,
+                    function: Some(function),
+                    inputs: inputs.names_json()?,
+                    ..Default::default()
+                },
+                self.options,
+            )?
+            .output()?;
+
+        format.deserialize(output)
+    }
+
+    /// see [`QuickJS::try_execute_lazy`]
+    pub fn try_execute_lazy(
+        self,
+        script: &str,
+        provider: Arc<dyn DataProvider>,
+    ) -> Result<Option<String>, ExecutionError> {
+        let output = self
+            .quickjs
+            .execute(
+                Invocation {
+                    script,
+                    lazy_data: Some(provider),
+                    ..Default::default()
+                },
+                self.options,
+            )?
+            .output()?;
+
+        decode_output(output)
+    }
+
+    /// see [`QuickJS::try_execute_module`]
+    pub fn try_execute_module(
+        self,
+        entry: &str,
+        export: &str,
+        resolver: Arc<dyn ModuleResolver>,
+        data: Option<&str>,
+    ) -> Result<Option<String>, ExecutionError> {
+        let modules = EntryModule::new(entry, export).to_json()?;
+        let data = data
+            .map(|data| data.as_bytes().to_vec())
+            .unwrap_or_default();
+
+        let output = self
+            .quickjs
+            .execute(
+                Invocation {
+                    data: data.into(),
+                    modules,
+                    resolver: Some(resolver),
+                    ..Default::default()
+                },
+                self.options,
+            )?
+            .output()?;
+
+        decode_output(output)
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{ExecutionError, PAGE_SIZE};
+use anyhow::{bail, Result};
+use std::sync::atomic::{AtomicU32, Ordering};
+use wasmtime::{Config, InstanceAllocationStrategy, PoolingAllocationConfig};
+
+/// Bounds the number of concurrent executions to the slots of the pooling instance allocator.
+#[derive(Debug)]
+pub(crate) struct Pool {
+    max_concurrency: u32,
+    in_flight: AtomicU32,
+}
+
+/// A reserved slot that is released when dropped.
+pub(crate) struct PoolPermit<'a> {
+    in_flight: &'a AtomicU32,
+}
+
+impl Pool {
+    /// configure the engine to allocate instances from a pool of `max_concurrency` slots each
+    /// sized to `memory_limit`
+    pub fn configure(config: &mut Config, max_concurrency: u32, memory_limit: u32) -> Self {
+        let mut pooling = PoolingAllocationConfig::default();
+        pooling
+            .total_core_instances(max_concurrency)
+            .total_memories(max_concurrency)
+            .total_tables(max_concurrency)
+            .memory_pages(u64::from(memory_limit / PAGE_SIZE))
+            // keep every slot warm so its memory can be reused
+            .max_unused_warm_slots(max_concurrency);
+
+        config
+            .allocation_strategy(InstanceAllocationStrategy::Pooling(pooling))
+            // map the wizer snapshot copy-on-write instead of copying it into each slot
+            .memory_init_cow(true);
+
+        Self {
+            max_concurrency,
+            in_flight: AtomicU32::new(0),
+        }
+    }
+
+    /// reserve a slot or fail immediately if every slot is in use
+    pub fn acquire(&self) -> Result<PoolPermit<'_>> {
+        let reserved =
+            self.in_flight
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |in_flight| {
+                    (in_flight < self.max_concurrency).then_some(in_flight + 1)
+                });
+
+        match reserved {
+            Ok(_) => Ok(PoolPermit {
+                in_flight: &self.in_flight,
+            }),
+            Err(_) => bail!(ExecutionError::PoolExhausted {
+                max_concurrency: self.max_concurrency
+            }),
+        }
+    }
+}
+
+impl Drop for PoolPermit<'_> {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::{
+    decode_output, snapshot::Snapshot, ticker::EpochTicker, Data, ExecutionError, ExecutionOptions,
+    Invocation, QuickJS, Run,
+};
+use anyhow::{anyhow, bail, Result};
+use serde_json::Value;
+use std::fmt::{self, Debug};
+use wasmtime::{Instance, TypedFunc};
+
+/// One store and instance reused across calls so globals, closures and caches defined by a call
+/// are still available to the next.
+///
+/// Created by [`QuickJS::session`]. The memory limit applies to the whole session while time and
+/// fuel limits apply to each call. A call that traps (i.e. a timeout) leaves the instance in an
+/// unknown state, every later call fails with [`ExecutionError::Host`].
+///
+/// The global `data` is reserved: every [`Session::eval`] rebinds it to its own data, or
+/// `undefined` without any, so state must be kept in other globals.
+///
+/// A session can be serialized with [`Session::snapshot`] and continued by [`QuickJS::restore`]
+/// in a fresh instance of the same module, possibly in another process.
+///
+/// ```no_run
+/// use quickjs::QuickJS;
+///
+/// let quickjs = QuickJS::builder().build().unwrap();
+/// let mut session = quickjs.session().unwrap();
+///
+/// session.eval("var count = 0", None).unwrap();
+/// session.eval("count += data.by", Some(r#"{"by": 2}"#)).unwrap();
+/// assert_eq!(session.eval("count", None).unwrap(), Some("2".to_string()));
+/// ```
+pub struct Session<'a> {
+    quickjs: &'a QuickJS,
+    run: Run<'a>,
+    instance: Instance,
+    entry: TypedFunc<(), ()>,
+    poisoned: bool,
+}
+
+impl<'a> Session<'a> {
+    pub(crate) fn new(quickjs: &'a QuickJS, snapshot: Option<&Snapshot>) -> Result<Self> {
+        if quickjs.async_support {
+            bail!("sessions are not supported with async_support");
+        }
+
+        let mut run = quickjs.prepare(Invocation::default(), None)?;
+        let instance = quickjs.instance_pre.instantiate(&mut run.store)?;
+        let entry = instance.get_typed_func::<(), ()>(&mut run.store, "quickjs.run")?;
+        if let Some(snapshot) = snapshot {
+            snapshot.restore(&mut run.store, instance, quickjs.module_hash)?;
+        }
+
+        Ok(Self {
+            quickjs,
+            run,
+            instance,
+            entry,
+            poisoned: false,
+        })
+    }
+
+    /// run the next call with per execution `options`, see [`QuickJS::with_options`]
+    pub fn with_options<'s>(
+        &'s mut self,
+        options: ExecutionOptions<'s>,
+    ) -> SessionExecutor<'s, 'a> {
+        SessionExecutor {
+            session: self,
+            options,
+        }
+    }
+
+    /// evaluate `script` with `data` bound to the global `data` and return the JSON encoded
+    /// result of the last expression. `var` and function declarations stay defined globally
+    /// except for `data`, which is replaced by the data of the next call to `eval`.
+    pub fn eval(
+        &mut self,
+        script: &str,
+        data: Option<&str>,
+    ) -> Result<Option<String>, ExecutionError> {
+        self.with_options(ExecutionOptions::default())
+            .eval(script, data)
+    }
+
+    /// call the global function `function` with `args` and return its result
+    pub fn call(&mut self, function: &str, args: &[Value]) -> Result<Value, ExecutionError> {
+        self.with_options(ExecutionOptions::default())
+            .call(function, args)
+    }
+
+    /// take what `console.log` wrote since the last call with `Stdio::Capture`
+    pub fn take_stdout(&self) -> Vec<u8> {
+        self.run
+            .stdout
+            .as_ref()
+            .map(|capture| capture.take())
+            .unwrap_or_default()
+    }
+
+    /// take what `console.error` wrote since the last call with `Stdio::Capture`
+    pub fn take_stderr(&self) -> Vec<u8> {
+        self.run
+            .stderr
+            .as_ref()
+            .map(|capture| capture.take())
+            .unwrap_or_default()
+    }
+
+    /// serialize the guest state so [`QuickJS::restore`] can continue it later, i.e. after a
+    /// deploy or to fork a warmed up session for many requests. the blob is about the size of
+    /// the session's memory.
+    pub fn snapshot(&mut self) -> Result<Vec<u8>, ExecutionError> {
+        if self.poisoned {
+            return Err(ExecutionError::Host(anyhow!(
+                "session is poisoned by a previous trap"
+            )));
+        }
+
+        let snapshot =
+            Snapshot::capture(&mut self.run.store, self.instance, self.quickjs.module_hash)?;
+        Ok(snapshot.encode()?)
+    }
+
+    /// whether a previous call trapped and the session can no longer be used
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    fn invoke(
+        &mut self,
+        invocation: Invocation<'_>,
+        options: ExecutionOptions<'_>,
+    ) -> Result<Option<Vec<u8>>> {
+        if self.poisoned {
+            bail!("session is poisoned by a previous trap");
+        }
+
+        // captures only hold the output of the current call
+        self.take_stdout();
+        self.take_stderr();
+
+        let _active = self.quickjs.ticker.as_ref().map(EpochTicker::activate);
+        self.run.store.data_mut().invoke(invocation);
+        self.quickjs.arm(&mut self.run.store, options.handle)?;
+
+        let result = self.entry.call(&mut self.run.store, ());
+        // the data may be borrowed for this call only
+        self.run.store.data_mut().data = Data::default();
+        if let Err(err) = result {
+            self.poisoned = true;
+            return Err(self
+                .quickjs
+                .classify(&mut self.run.store, self.instance, err));
+        }
+
+        self.quickjs.report(&self.run, options.output)?;
+        Ok(self.run.store.data_mut().output.take().transpose()?)
+    }
+}
+
+/// The calls of a [`Session`] with [`ExecutionOptions`], created by [`Session::with_options`].
+#[derive(Debug)]
+pub struct SessionExecutor<'s, 'a> {
+    session: &'s mut Session<'a>,
+    options: ExecutionOptions<'s>,
+}
+
+impl SessionExecutor<'_, '_> {
+    /// see [`Session::eval`]
+    pub fn eval(self, script: &str, data: Option<&str>) -> Result<Option<String>, ExecutionError> {
+        let data = data
+            .map(|data| data.as_bytes().to_vec())
+            .unwrap_or_default();
+
+        let output = self.session.invoke(
+            Invocation {
+                script,
+                data: data.into(),
+                ..Default::default()
+            },
+            self.options,
+        )?;
+
+        decode_output(output)
+    }
+
+    /// see [`Session::call`]
+    pub fn call(self, function: &str, args: &[Value]) -> Result<Value, ExecutionError> {
+        let format = self.session.quickjs.wire_format;
+        let data = format.serialize(args)?;
+
+        let output = self.session.invoke(
+            Invocation {
+                data: data.into(),
+                format,
+                function: Some(function),
+                ..Default::default()
+            },
+            self.options,
+        )?;
+
+        format.deserialize(output)
+    }
+}
+
+impl Debug for Session<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Session")
+            .field("quickjs", &self.quickjs)
+            .field("poisoned", &self.poisoned)
+            .finish()
+    }
+}
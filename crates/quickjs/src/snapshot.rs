@@ -0,0 +1,179 @@
+use crate::{ExecutionError, State, PAGE_SIZE};
+use anyhow::{anyhow, bail, Result};
+use wasmtime::{Extern, Instance, Mutability, Store, Val};
+
+static MAGIC: &[u8; 4] = b"QJSS";
+static VERSION: u32 = 1;
+
+/// The guest state of a [`Session`](crate::Session) between two calls.
+///
+/// Only the linear memory and the exported mutable globals are captured. The quickjs heap and
+/// every guest static live in linear memory and the stack pointer is back at its initial value
+/// between calls. WASI keeps no state across calls as stdio is configured by the builder.
+///
+/// Encoded as `QJSS`, a little endian u32 version, the sha256 of the module, the globals as
+/// `(name, type, value)` and the memory.
+pub(crate) struct Snapshot {
+    module_hash: [u8; 32],
+    globals: Vec<(String, Val)>,
+    memory: Vec<u8>,
+}
+
+impl Snapshot {
+    /// capture the state of `instance`
+    pub fn capture(
+        store: &mut Store<State>,
+        instance: Instance,
+        module_hash: [u8; 32],
+    ) -> Result<Self> {
+        let mut globals = Vec::new();
+        let mut memory = None;
+        let exports = instance
+            .exports(&mut *store)
+            .map(|export| (export.name().to_string(), export.into_extern()))
+            .collect::<Vec<_>>();
+        for (name, export) in exports {
+            match export {
+                Extern::Global(global) if global.ty(&*store).mutability() == Mutability::Var => {
+                    globals.push((name, global.get(&mut *store)));
+                }
+                Extern::Memory(export) if name == "memory" => {
+                    memory = Some(export.data(&*store).to_vec());
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            module_hash,
+            globals,
+            memory: memory.ok_or_else(|| anyhow!("module does not export its memory"))?,
+        })
+    }
+
+    /// restore the state into a fresh `instance` of the module it was captured from
+    pub fn restore(
+        &self,
+        store: &mut Store<State>,
+        instance: Instance,
+        module_hash: [u8; 32],
+    ) -> Result<()> {
+        if self.module_hash != module_hash {
+            bail!(invalid("snapshot was taken from a different module"));
+        }
+
+        let memory = instance
+            .get_memory(&mut *store, "memory")
+            .ok_or_else(|| anyhow!("module does not export its memory"))?;
+        let pages = (self.memory.len() / PAGE_SIZE as usize) as u64;
+        let grow = pages.saturating_sub(memory.size(&*store));
+        if grow > 0 {
+            // fails when the snapshot exceeds the memory limit
+            memory.grow(&mut *store, grow)?;
+        }
+        memory.write(&mut *store, 0, &self.memory)?;
+
+        for (name, value) in &self.globals {
+            instance
+                .get_global(&mut *store, name)
+                .ok_or_else(|| invalid(&format!("module has no global {name}")))?
+                .set(&mut *store, value.clone())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(self.memory.len() + 1024);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.module_hash);
+
+        bytes.extend_from_slice(&(self.globals.len() as u32).to_le_bytes());
+        for (name, value) in &self.globals {
+            bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+            let (kind, bits) = match value {
+                Val::I32(value) => (0u8, *value as u32 as u64),
+                Val::I64(value) => (1, *value as u64),
+                Val::F32(bits) => (2, *bits as u64),
+                Val::F64(bits) => (3, *bits),
+                _ => bail!("global {name} of type {:?} cannot be snapshot", value.ty()),
+            };
+            bytes.push(kind);
+            bytes.extend_from_slice(&bits.to_le_bytes());
+        }
+
+        bytes.extend_from_slice(&(self.memory.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&self.memory);
+        Ok(bytes)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader(bytes);
+        if reader.take(MAGIC.len())? != MAGIC {
+            bail!(invalid("not a session snapshot"));
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            bail!(invalid(&format!("unsupported snapshot version {version}")));
+        }
+        let module_hash: [u8; 32] = reader.take(32)?.try_into()?;
+
+        let count = reader.u32()?;
+        let mut globals = Vec::new();
+        for _ in 0..count {
+            let len = reader.u32()? as usize;
+            let name = String::from_utf8(reader.take(len)?.to_vec())
+                .map_err(|_| invalid("snapshot global name is not utf-8"))?;
+            let kind = reader.take(1)?[0];
+            let bits = reader.u64()?;
+            let value = match kind {
+                0 => Val::I32(bits as u32 as i32),
+                1 => Val::I64(bits as i64),
+                2 => Val::F32(bits as u32),
+                3 => Val::F64(bits),
+                _ => bail!(invalid(&format!("unknown type of snapshot global {name}"))),
+            };
+            globals.push((name, value));
+        }
+
+        let len = reader.u64()? as usize;
+        let memory = reader.take(len)?.to_vec();
+        if !len.is_multiple_of(PAGE_SIZE as usize) || !reader.0.is_empty() {
+            bail!(invalid("snapshot memory is corrupt"));
+        }
+
+        Ok(Self {
+            module_hash,
+            globals,
+            memory,
+        })
+    }
+}
+
+fn invalid(message: &str) -> ExecutionError {
+    ExecutionError::InvalidInput(anyhow!("{message}").into())
+}
+
+/// Reads the fields of an encoded snapshot.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            bail!(invalid("snapshot is truncated"));
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+}
@@ -0,0 +1,78 @@
+use crate::ExecutionError;
+use anyhow::{bail, Context, Result};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// the directory the guest reads the script from and writes its exception to
+static GUEST_DIR: &str = "/specialize";
+
+/// Runs `script` once in an instance of `wasm` and snapshots the initialized memory into a new
+/// wasm module with the `wizer` CLI, which must be on the `PATH`.
+///
+/// wizer runs in its own process since it links a wasmtime version of its own.
+pub(crate) fn specialize(wasm: &[u8], script: &str) -> Result<Vec<u8>> {
+    let dir = TempDir::new()?;
+    let guest_dir = dir.path().join("guest");
+    fs::create_dir(&guest_dir)?;
+    fs::write(guest_dir.join("script.js"), script)?;
+    let input = dir.path().join("input.wasm");
+    let output = dir.path().join("output.wasm");
+    fs::write(&input, wasm)?;
+
+    let result = Command::new("wizer")
+        .arg("--allow-wasi")
+        .args(["--init-func", "quickjs.specialize"])
+        .args(["--wasm-bulk-memory", "true"])
+        .arg("--mapdir")
+        .arg(format!("{GUEST_DIR}::{}", guest_dir.display()))
+        .arg("-o")
+        .arg(&output)
+        .arg(&input)
+        .output()
+        .context("failed to run wizer, is it installed?")?;
+
+    if result.status.success() {
+        return Ok(fs::read(&output)?);
+    }
+    match fs::read(guest_dir.join("error")) {
+        // the script threw while initializing
+        Ok(exception) => bail!(ExecutionError::from_exception(&exception)),
+        Err(_) => bail!(
+            "failed to specialize the module: {}",
+            String::from_utf8_lossy(&result.stderr).trim()
+        ),
+    }
+}
+
+/// A directory removed when dropped.
+struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    fn new() -> Result<Self> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let path = env::temp_dir().join(format!(
+            "quickjs-specialize-{}-{}",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&path)?;
+        Ok(Self { path })
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.path).ok();
+    }
+}
@@ -0,0 +1,133 @@
+use anyhow::Result;
+use std::{
+    fmt,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use wasmtime::Engine;
+
+/// Increments the epoch of an engine every `interval` while executions are in flight.
+///
+/// The thread sleeps while no execution is active and is stopped and joined on drop.
+pub(crate) struct EpochTicker {
+    shared: Arc<Shared>,
+    interval: Duration,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Marks an execution as in flight until dropped.
+pub(crate) struct ActiveGuard<'a> {
+    shared: &'a Shared,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<TickerState>,
+    condvar: Condvar,
+}
+
+#[derive(Default)]
+struct TickerState {
+    active: usize,
+    shutdown: bool,
+}
+
+impl EpochTicker {
+    pub fn spawn(engine: Engine, interval: Duration) -> Result<Self> {
+        let shared = Arc::new(Shared::default());
+        let thread = thread::Builder::new()
+            .name("quickjs-epoch-ticker".to_string())
+            .spawn({
+                let shared = shared.clone();
+                move || shared.run(&engine, interval)
+            })?;
+
+        Ok(Self {
+            shared,
+            interval,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// start ticking, if idle, until the guard is dropped
+    pub fn activate(&self) -> ActiveGuard<'_> {
+        let mut state = self.shared.lock();
+        state.active += 1;
+        if state.active == 1 {
+            self.shared.condvar.notify_all();
+        }
+        ActiveGuard {
+            shared: &self.shared,
+        }
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, TickerState> {
+        // the state stays consistent even if a holder panicked
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn run(&self, engine: &Engine, interval: Duration) {
+        let mut state = self.lock();
+        loop {
+            if state.shutdown {
+                return;
+            }
+            if state.active == 0 {
+                state = self
+                    .condvar
+                    .wait(state)
+                    .unwrap_or_else(|err| err.into_inner());
+                continue;
+            }
+
+            // wait a full interval, notifications only interrupt it to shut down
+            let tick = Instant::now() + interval;
+            while let Some(timeout) = tick.checked_duration_since(Instant::now()) {
+                if state.shutdown || timeout.is_zero() {
+                    break;
+                }
+                state = self
+                    .condvar
+                    .wait_timeout(state, timeout)
+                    .unwrap_or_else(|err| err.into_inner())
+                    .0;
+            }
+
+            if !state.shutdown && state.active > 0 {
+                engine.increment_epoch();
+            }
+        }
+    }
+}
+
+impl Drop for EpochTicker {
+    fn drop(&mut self) {
+        self.shared.lock().shutdown = true;
+        self.shared.condvar.notify_all();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+impl Drop for ActiveGuard<'_> {
+    fn drop(&mut self) {
+        self.shared.lock().active -= 1;
+    }
+}
+
+impl fmt::Debug for EpochTicker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EpochTicker")
+            .field("interval", &self.interval)
+            .field("active", &self.shared.lock().active)
+            .finish()
+    }
+}